@@ -1,8 +1,36 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 
+//! Inputs of the player, from the keyboard and the gamepads. The keys and
+//! buttons are read from `bindings.ron` in the data directory, written with
+//! the default ones the first time the game starts, and changed on the screen
+//! of [`crate::controls`]. The keys are the names of Bevy's `KeyCode`, so ZQSD
+//! on an AZERTY keyboard is:
+//!
+//! ```ron
+//! keys: [(Z, Up), (S, Down), (Q, Left), (D, Right), (Space, Special)],
+//! ```
+
+use std::collections::HashMap;
+
+pub use crate::board::GameControl;
+use crate::pause::PauseState;
+use crate::replay::ReplayPlayback;
+use crate::save::{data_dir, load_ron, save_ron};
 use crate::GameState;
 use bevy::prelude::*;
 use colored::*;
+use serde::{Deserialize, Serialize};
+
+pub const BINDINGS_FILE: &str = "bindings.ron";
+pub const STICK_DEADZONE: f32 = 0.5;
+// Order in which the controls are checked, the first one triggered wins:
+pub const CONTROLS: [GameControl; 5] = [
+    GameControl::Up,
+    GameControl::Down,
+    GameControl::Left,
+    GameControl::Right,
+    GameControl::Special,
+];
 
 pub struct ActionsPlugin;
 
@@ -12,11 +40,16 @@ impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
             Update,
-            set_movement_actions.run_if(in_state(GameState::Playing)),
+            set_movement_actions
+                .run_if(in_state(GameState::Playing))
+                .run_if(in_state(PauseState::Running))
+                // During a replay, the inputs come from the replay file:
+                .run_if(not(resource_exists::<ReplayPlayback>())),
         )
         .insert_resource(Actions {
             next_action: GameControl::Idle,
-        });
+        })
+        .insert_resource(load_bindings());
     }
 }
 
@@ -25,8 +58,123 @@ pub struct Actions {
     pub next_action: GameControl,
 }
 
-fn set_movement_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<KeyCode>>) {
-    let received_input = match_input(keyboard_input);
+/// Keys and gamepad buttons of each control. A control can have several of
+/// them, and the left stick of the gamepads always moves the player.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+    pub keys: Vec<(KeyCode, GameControl)>,
+    pub buttons: Vec<(GamepadButtonType, GameControl)>,
+    /// How far the left stick has to be pushed to count as a direction,
+    /// between 0 and 1
+    pub stick_deadzone: f32,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            keys: vec![
+                (KeyCode::W, GameControl::Up),
+                (KeyCode::Up, GameControl::Up),
+                (KeyCode::S, GameControl::Down),
+                (KeyCode::Down, GameControl::Down),
+                (KeyCode::A, GameControl::Left),
+                (KeyCode::Left, GameControl::Left),
+                (KeyCode::D, GameControl::Right),
+                (KeyCode::Right, GameControl::Right),
+                (KeyCode::Space, GameControl::Special),
+            ],
+            buttons: vec![
+                (GamepadButtonType::DPadUp, GameControl::Up),
+                (GamepadButtonType::DPadDown, GameControl::Down),
+                (GamepadButtonType::DPadLeft, GameControl::Left),
+                (GamepadButtonType::DPadRight, GameControl::Right),
+                (GamepadButtonType::South, GameControl::Special),
+                (GamepadButtonType::East, GameControl::Special),
+            ],
+            stick_deadzone: STICK_DEADZONE,
+        }
+    }
+}
+
+impl Bindings {
+    pub fn keys_of(&self, control: GameControl) -> impl Iterator<Item = KeyCode> + '_ {
+        self.keys
+            .iter()
+            .filter(move |x| x.1 == control)
+            .map(|x| x.0)
+    }
+
+    pub fn buttons_of(&self, control: GameControl) -> impl Iterator<Item = GamepadButtonType> + '_ {
+        self.buttons
+            .iter()
+            .filter(move |x| x.1 == control)
+            .map(|x| x.0)
+    }
+}
+
+fn load_bindings() -> Bindings {
+    let path = data_dir().join(BINDINGS_FILE);
+    if path.exists() {
+        match load_ron::<Bindings>(&path) {
+            Ok(bindings) => return bindings,
+            Err(err) => error!("could not load the bindings {:?}: {}", path, err),
+        }
+    }
+    let bindings = Bindings::default();
+    if !path.exists() {
+        match save_ron(&path, &bindings) {
+            Ok(()) => info!("edit {:?} to change the controls", path),
+            Err(err) => error!("could not save the bindings {:?}: {}", path, err),
+        }
+    }
+    bindings
+}
+
+/// Direction the left stick of a gamepad points to, Idle inside the deadzone
+fn stick_direction(x: f32, y: f32, deadzone: f32) -> GameControl {
+    if x.abs().max(y.abs()) < deadzone {
+        GameControl::Idle
+    } else if x.abs() > y.abs() {
+        if x > 0. {
+            GameControl::Right
+        } else {
+            GameControl::Left
+        }
+    } else if y > 0. {
+        GameControl::Up
+    } else {
+        GameControl::Down
+    }
+}
+
+fn set_movement_actions(
+    mut actions: ResMut<Actions>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    button_input: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    bindings: Res<Bindings>,
+    mut stick_directions: Local<HashMap<Gamepad, GameControl>>,
+) {
+    let mut received_input = match_input(&keyboard_input, &bindings);
+    for gamepad in gamepads.iter() {
+        if received_input == GameControl::Idle {
+            received_input = match_gamepad_input(gamepad, &button_input, &bindings);
+        }
+        // The stick moves once when it leaves the center or changes of
+        // direction, not every frame it is held:
+        let x = axes
+            .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+            .unwrap_or(0.);
+        let y = axes
+            .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))
+            .unwrap_or(0.);
+        let direction = stick_direction(x, y, bindings.stick_deadzone);
+        let previous = stick_directions.insert(gamepad, direction);
+        if received_input == GameControl::Idle && previous != Some(direction) {
+            received_input = direction;
+        }
+    }
     match received_input {
         // If Idle, do nothing
         GameControl::Idle => (),
@@ -38,34 +186,39 @@ fn set_movement_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<
     };
 }
 
-///Enum for the direction. Idle has been added to be able to use an array buffer
-/// instead of a vector.
-#[derive(Default, Eq, PartialEq, Debug, Copy, Clone, Resource)]
-pub enum GameControl {
-    #[default]
-    Idle,
-    Up,
-    Down,
-    Left,
-    Right,
-    Special,
+/// The moves are played when their key is released, the special when its key
+/// is pressed
+fn match_input(keyboard_input: &Input<KeyCode>, bindings: &Bindings) -> GameControl {
+    for control in CONTROLS {
+        let mut keys = bindings.keys_of(control);
+        let triggered = match control {
+            GameControl::Special => keys.any(|x| keyboard_input.just_pressed(x)),
+            _ => keys.any(|x| keyboard_input.just_released(x)),
+        };
+        if triggered {
+            return control;
+        }
+    }
+    GameControl::Idle
 }
 
-fn match_input(keyboard_input: Res<Input<KeyCode>>) -> GameControl {
-    if keyboard_input.just_released(KeyCode::W) || keyboard_input.just_released(KeyCode::Up) {
-        return GameControl::Up;
-    }
-    if keyboard_input.just_released(KeyCode::S) || keyboard_input.just_released(KeyCode::Down) {
-        return GameControl::Down;
-    }
-    if keyboard_input.just_released(KeyCode::A) || keyboard_input.just_released(KeyCode::Left) {
-        return GameControl::Left;
-    }
-    if keyboard_input.just_released(KeyCode::D) || keyboard_input.just_released(KeyCode::Right) {
-        return GameControl::Right;
-    }
-    if keyboard_input.just_pressed(KeyCode::Space) {
-        return GameControl::Special;
+/// Same as [`match_input`] with the buttons of a gamepad
+fn match_gamepad_input(
+    gamepad: Gamepad,
+    button_input: &Input<GamepadButton>,
+    bindings: &Bindings,
+) -> GameControl {
+    for control in CONTROLS {
+        let mut buttons = bindings
+            .buttons_of(control)
+            .map(|x| GamepadButton::new(gamepad, x));
+        let triggered = match control {
+            GameControl::Special => buttons.any(|x| button_input.just_pressed(x)),
+            _ => buttons.any(|x| button_input.just_released(x)),
+        };
+        if triggered {
+            return control;
+        }
     }
     GameControl::Idle
 }
@@ -3,15 +3,32 @@
 use std::time::Duration;
 
 use crate::actions::{Actions, GameControl};
-use crate::loading::AudioAssets;
-use crate::logic::{CloudControl, MainClock, SPAWN_FREQUENCY, TIMER_SCALE_FACTOR};
-use crate::GameState;
+use crate::practice::PracticeSettings;
+use crate::settings::Settings;
+use crate::songs::{CurrentSong, SelectedSong, SongDefinition};
+use crate::{GameMode, GameState};
 use bevy::prelude::*;
 use bevy_kira_audio::prelude::*;
 use colored::*;
 
+// Length of the snippet looped in the menu, in seconds:
+pub const PREVIEW_LENGTH: f64 = 12.;
+const PREVIEW_FADE: Duration = Duration::from_millis(500);
+// Volume of the push samples, louder than the moves:
+const PUSH_VOLUME: f64 = 3.;
+
 pub struct InternalAudioPlugin;
 
+/// Channel of the songs, in the runs, the menu and the editor
+#[derive(Resource)]
+pub struct MusicChannel;
+/// Channel of the samples played by the player, and of the game over stinger
+#[derive(Resource)]
+pub struct SfxChannel;
+/// Channel of the sounds of the menus, like the metronome of the calibration
+#[derive(Resource)]
+pub struct UiChannel;
+
 #[derive(Default, Event)]
 pub struct SoundOnMove;
 
@@ -20,118 +37,163 @@ pub struct SoundOnAction {
     pub direction: GameControl,
 }
 
-#[derive(Default, Eq, PartialEq, Debug, Copy, Clone)]
-pub enum SelectedSong {
-    Song1,
-    #[default]
-    Song2,
-}
-
 #[derive(Resource)]
 pub struct SoundTracker {
     pub special_ndx: u8,
 }
 
-pub struct SongInfo {
-    pub length: f32,
-    pub beat_length: f32,
-    pub intro_length: f32,
-}
-
-pub const SONG_1: SongInfo = SongInfo {
-    length: 60.,
-    beat_length: 0.600,
-    intro_length: 2.400,
-};
-
-pub const SONG_2: SongInfo = SongInfo {
-    length: 60. - 2.4,
-    beat_length: 0.600,
-    //WIP
-    // beat_length: 0.100,
-    intro_length: 2.400,
-};
-
 #[derive(Resource)]
 pub struct SongHandle {
     pub song: Handle<AudioInstance>,
 }
 
+/// Snippet of the selected song, played while in the menu
+#[derive(Default, Resource)]
+pub struct SongPreview {
+    pub instance: Option<Handle<AudioInstance>>,
+    song: Handle<SongDefinition>,
+}
+
 // This plugin is responsible to control the game audio
 impl Plugin for InternalAudioPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(AudioPlugin)
+            .add_audio_channel::<MusicChannel>()
+            .add_audio_channel::<SfxChannel>()
+            .add_audio_channel::<UiChannel>()
+            .init_resource::<SongPreview>()
+            .add_systems(Update, play_song_preview.run_if(in_state(GameState::Menu)))
+            .add_systems(OnExit(GameState::Menu), stop_song_preview)
             .add_systems(OnEnter(GameState::Playing), play_music)
+            .add_systems(OnEnter(GameState::GameOver), play_game_over_stinger)
             .add_systems(
                 Update,
-                play_debug_beep_on_spawn.run_if(in_state(GameState::Playing)),
+                (play_sound_on_move, play_sound_on_push).run_if(in_state(GameState::Playing)),
             )
             .add_systems(
                 Update,
-                (play_sound_on_move, play_sound_on_push).run_if(in_state(GameState::Playing)),
+                apply_channel_volumes.run_if(resource_changed::<Settings>()),
             );
     }
 }
 
-fn play_music(audio_assets: Res<AudioAssets>, audio: Res<Audio>, mut commands: Commands) {
-    let handle: Handle<AudioInstance>;
+fn play_music(
+    current_song: CurrentSong,
+    music: Res<AudioChannel<MusicChannel>>,
+    game_mode: Res<GameMode>,
+    practice: Res<PracticeSettings>,
+    mut commands: Commands,
+) {
     commands.insert_resource(SoundTracker { special_ndx: 0 });
-    if audio_assets.selected_song == SelectedSong::Song1 {
-        handle = audio.play(audio_assets.song_1.clone()).handle();
-        commands.insert_resource(SongHandle { song: handle });
-    } else if audio_assets.selected_song == SelectedSong::Song2 {
-        handle = audio.play(audio_assets.song_2.clone()).handle();
-        commands.insert_resource(SongHandle { song: handle });
+    let song = current_song.get();
+    let handle = music
+        .play(song.track.clone())
+        .with_volume(song.volume)
+        .with_playback_rate(practice.playback_rate(*game_mode) as f64)
+        .with_panning(0.5)
+        .loop_from(song.intro_length as f64)
+        .loop_until((song.intro_length + song.loop_length) as f64)
+        .handle();
+    commands.insert_resource(SongHandle { song: handle });
+}
+
+/// Loop a snippet of the selected song, and switch when another one is chosen
+fn play_song_preview(
+    current_song: CurrentSong,
+    selected_song: Res<SelectedSong>,
+    music: Res<AudioChannel<MusicChannel>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut preview: ResMut<SongPreview>,
+) {
+    if preview.instance.is_some() && preview.song == selected_song.handle {
+        return;
+    }
+    stop_preview(&mut audio_instances, &mut preview);
+    let song = current_song.get();
+    let start = song.preview_start as f64;
+    let handle = music
+        .play(song.track.clone())
+        .start_from(start)
+        .loop_from(start)
+        .loop_until(start + PREVIEW_LENGTH)
+        .with_volume(song.volume)
+        .fade_in(AudioTween::linear(PREVIEW_FADE))
+        .handle();
+    preview.instance = Some(handle);
+    preview.song = selected_song.handle.clone();
+}
+
+fn stop_song_preview(
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut preview: ResMut<SongPreview>,
+) {
+    stop_preview(&mut audio_instances, &mut preview);
+}
+
+fn stop_preview(audio_instances: &mut Assets<AudioInstance>, preview: &mut SongPreview) {
+    if let Some(handle) = preview.instance.take() {
+        if let Some(instance) = audio_instances.get_mut(&handle) {
+            instance.stop(AudioTween::linear(PREVIEW_FADE));
+        }
+    }
+}
+
+/// The volumes of the settings are the ones of the channels, the sounds only
+/// carry their volume relative to the others
+fn apply_channel_volumes(
+    settings: Res<Settings>,
+    music: Res<AudioChannel<MusicChannel>>,
+    sfx: Res<AudioChannel<SfxChannel>>,
+    ui: Res<AudioChannel<UiChannel>>,
+) {
+    music.set_volume(settings.music_volume());
+    sfx.set_volume(settings.sfx_volume());
+    ui.set_volume(settings.ui_volume());
+}
+
+/// The song fades out on the music channel, the stinger of the song being
+/// played over it
+fn play_game_over_stinger(current_song: CurrentSong, sfx: Res<AudioChannel<SfxChannel>>) {
+    if let Some(sample) = &current_song.get().game_over_sample {
+        sfx.play(sample.clone()).with_volume(PUSH_VOLUME);
     }
 }
 
 fn play_sound_on_move(
     mut play_sound_events: EventReader<SoundOnMove>,
-    audio: Res<Audio>,
-    audio_assets: Res<AudioAssets>,
+    sfx: Res<AudioChannel<SfxChannel>>,
+    current_song: CurrentSong,
 ) {
     for _ in play_sound_events.iter() {
-        audio.play(audio_assets.sample_1_c.clone());
+        sfx.play(current_song.get().move_sample.clone());
     }
 }
 
 fn play_sound_on_push(
     mut play_sound_events: EventReader<SoundOnAction>,
-    audio: Res<Audio>,
-    audio_assets: Res<AudioAssets>,
+    sfx: Res<AudioChannel<SfxChannel>>,
+    current_song: CurrentSong,
     mut sound_tracker: ResMut<SoundTracker>,
 ) {
+    let song = current_song.get();
     for event in play_sound_events.iter() {
-        match event.direction {
-            GameControl::Up => {
-                audio.play(audio_assets.sample_1_a.clone()).with_volume(3.);
-            }
-            GameControl::Down => {
-                audio.play(audio_assets.sample_1_b.clone()).with_volume(3.);
-            }
-            GameControl::Left => {
-                audio.play(audio_assets.sample_1_c.clone()).with_volume(3.);
-            }
-            GameControl::Right => {
-                audio.play(audio_assets.sample_1_d.clone()).with_volume(3.);
-            }
+        let sample = match event.direction {
+            GameControl::Up => &song.push_samples.up,
+            GameControl::Down => &song.push_samples.down,
+            GameControl::Left => &song.push_samples.left,
+            GameControl::Right => &song.push_samples.right,
             GameControl::Special => {
-                if sound_tracker.special_ndx >= 4 {
-                    sound_tracker.special_ndx = 0
-                }
-                if sound_tracker.special_ndx == 0 {
-                    audio.play(audio_assets.sample_3_a.clone()).with_volume(3.);
-                } else if sound_tracker.special_ndx == 1 {
-                    audio.play(audio_assets.sample_3_c.clone()).with_volume(3.);
-                } else if sound_tracker.special_ndx == 2 {
-                    audio.play(audio_assets.sample_3_b.clone()).with_volume(3.);
-                } else if sound_tracker.special_ndx == 3 {
-                    audio.play(audio_assets.sample_3_d.clone()).with_volume(3.);
+                // The special samples are played one after the other:
+                if song.special_samples.is_empty() {
+                    continue;
                 }
-                sound_tracker.special_ndx += 1;
+                let ndx = sound_tracker.special_ndx as usize % song.special_samples.len();
+                sound_tracker.special_ndx = (ndx + 1) as u8;
+                &song.special_samples[ndx]
             }
-            GameControl::Idle => (),
-        }
+            GameControl::Idle => continue,
+        };
+        sfx.play(sample.clone()).with_volume(PUSH_VOLUME);
     }
 }
 
@@ -147,20 +209,3 @@ fn play_sound_on_push(
 // pub sample_3_b: Handle<AudioInstance>,
 // pub sample_3_c: Handle<AudioInstance>,
 // pub sample_3_d: Handle<AudioInstance>,
-
-// fn stop_music(audio: Res<Audio>) {
-//     audio.stop().fade_out(AudioTween::new(
-//         Duration::from_secs(1),
-//         AudioEasing::InOutPowi(2),
-//     ));
-// }
-
-fn play_debug_beep_on_spawn(
-    main_clock: Res<MainClock>,
-    _audio_assets: Res<AudioAssets>,
-    _audio: Res<Audio>,
-) {
-    if main_clock.move_clouds {
-        // audio.play(audio_assets.debug_beep.clone());
-    }
-}
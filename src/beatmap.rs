@@ -0,0 +1,90 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Charts describing the cloud choreography of a song, beat by beat.
+//!
+//! Without a chart, the board moves one direction of `SEQUENCE` per beat and
+//! spawns on its border every `spawn_frequency` turns, so every song plays the
+//! same. A chart replaces this with the moves and spawns of each beat, to
+//! follow the phrasing of the music. It is referenced by the `beatmap` field
+//! of a `.song.ron` file:
+//!
+//! ```ron
+//! (
+//!     // Played once, while the intro of the song plays:
+//!     intro: [(), (), (), ()],
+//!     // Then looped, like the song:
+//!     beats: [
+//!         (moves: [Left], spawns: [Left]),
+//!         (moves: [Up]),
+//!         (moves: [Right], spawns: [Right, Up]),
+//!         (moves: [Down, Up]),
+//!     ],
+//! )
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{BoardConfig, CloudDir, SEQUENCE, SPAWN_OFFSET};
+
+/// What happens on one beat of a chart
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChartBeat {
+    /// The clouds going in these directions move by one tile. The cooldown
+    /// of a pushed cloud is counted in beats, moves or not.
+    #[serde(default)]
+    pub moves: Vec<CloudDir>,
+    /// A cloud going in each of these directions spawns on the opposite border
+    #[serde(default)]
+    pub spawns: Vec<CloudDir>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Beatmap {
+    #[serde(default)]
+    pub intro: Vec<ChartBeat>,
+    pub beats: Vec<ChartBeat>,
+}
+
+impl Beatmap {
+    /// The chart of a beat, counted from the start of the run. `None` once
+    /// the intro is over if there is no looped part.
+    pub fn beat(&self, beat: u32) -> Option<&ChartBeat> {
+        let beat = beat as usize;
+        if beat < self.intro.len() {
+            return self.intro.get(beat);
+        }
+        if self.beats.is_empty() {
+            return None;
+        }
+        self.beats.get((beat - self.intro.len()) % self.beats.len())
+    }
+
+    /// The chart played by the default rules, as a starting point to write a
+    /// new one
+    pub fn from_config(config: &BoardConfig) -> Self {
+        let spawn_frequency = config.spawn_frequency.max(1) as usize;
+        let mut spawn_counter = SPAWN_OFFSET.map(|x| x as usize);
+        // The pattern repeats once every direction went through a full
+        // spawn cycle:
+        let beats = (0..SEQUENCE.len() * spawn_frequency)
+            .map(|ndx| {
+                // The first beat of a board moves the second direction:
+                let dir_ndx = (ndx + 1) % SEQUENCE.len();
+                let dir = SEQUENCE[dir_ndx];
+                spawn_counter[dir_ndx] = (spawn_counter[dir_ndx] + 1) % spawn_frequency;
+                ChartBeat {
+                    moves: vec![dir],
+                    spawns: if spawn_counter[dir_ndx] == 0 {
+                        vec![dir]
+                    } else {
+                        Vec::new()
+                    },
+                }
+            })
+            .collect();
+        Beatmap {
+            intro: Vec::new(),
+            beats,
+        }
+    }
+}
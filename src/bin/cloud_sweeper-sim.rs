@@ -0,0 +1,333 @@
+//! Headless simulation of the game rules, used to balance the game.
+//!
+//! The board is stepped on the beat grid by a bot, without any window or
+//! audio. Example:
+//!
+//! ```sh
+//! cargo run --bin cloud_sweeper-sim --no-default-features -- --seed 42 --bot greedy --ticks 20000 --runs 10
+//! ```
+//!
+//! Without the default `game` feature, only the rules are built, not Bevy.
+//!
+//! Options:
+//! - `--seed <n>`: seed of the first run, the next runs use the following
+//!   seeds (random by default)
+//! - `--runs <n>`: number of runs (1 by default)
+//! - `--bot <idle|random|greedy>`: policy of the player (greedy by default)
+//! - `--ticks <n>`: maximum number of ticks per run
+//! - `--beat-length <s>`: only used to convert the survival time in seconds
+//! - `--difficulty <easy|normal|hard>`: rules to start from (normal by
+//!   default)
+//! - `--spawn-frequency <n>`, `--push-cooldown <beats>`, `--mess-limit <n>`,
+//!   `--special-timeout <ticks>`: override the rules, see `BoardConfig`
+//! - `--beatmap <file>`: follow the chart of a `.beatmap.ron` file instead of
+//!   the direction sequence
+//! - `--board-size <w>x<h>`, `--stage-size <w>x<h>`: play on another board,
+//!   with the stage in its middle (10x10 and 6x6 by default)
+//! - `--stage <file>`: play on the stage drawn in a file like
+//!   `assets/default.stage.ron`, instead of a rectangle
+//! - `--ramp <file>`: make the rules harder as the run goes on, following a
+//!   ramp like `assets/default.ramp.ron`
+//! - `--puzzle <file>`: play a puzzle of `assets/puzzles` instead, each run
+//!   ending once it is solved or failed
+
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+
+use cloud_sweeper::beatmap::Beatmap;
+use cloud_sweeper::board::{
+    Board, BoardConfig, BoardGeometry, GameControl, GameRng, LossCondition, StageMap,
+    TIMER_SCALE_FACTOR,
+};
+use cloud_sweeper::puzzle::{Puzzle, PuzzleStatus};
+use cloud_sweeper::ramp::Ramp;
+
+const DEFAULT_TICKS: u32 = 10_000;
+const DEFAULT_BEAT_LENGTH: f32 = 0.6;
+const MOVES: [GameControl; 5] = [
+    GameControl::Up,
+    GameControl::Down,
+    GameControl::Left,
+    GameControl::Right,
+    GameControl::Special,
+];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Bot {
+    /// Never moves
+    Idle,
+    /// Plays a random move on every beat
+    Random,
+    /// Plays the move leaving the least clouds on the stage after the beat
+    Greedy,
+}
+
+impl FromStr for Bot {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "idle" => Ok(Bot::Idle),
+            "random" => Ok(Bot::Random),
+            "greedy" => Ok(Bot::Greedy),
+            _ => Err(format!("unknown bot '{}'", s)),
+        }
+    }
+}
+
+impl Bot {
+    fn next_input(&self, board: &Board, rng: &mut GameRng) -> GameControl {
+        match self {
+            Bot::Idle => GameControl::Idle,
+            Bot::Random => rng.choose(&MOVES).unwrap_or_default(),
+            Bot::Greedy => {
+                // Look one beat ahead for every move, and keep the best ones:
+                let mut best_score = usize::MAX;
+                let mut best_moves = Vec::new();
+                for input in MOVES.into_iter().chain([GameControl::Idle]) {
+                    let mut future = board.clone();
+                    future.step(input);
+                    for _ in 1..TIMER_SCALE_FACTOR {
+                        future.step(GameControl::Idle);
+                    }
+                    let score = match future.loss_condition {
+                        LossCondition::NoLoss => future.mess,
+                        _ => usize::MAX - 1,
+                    };
+                    if score < best_score {
+                        best_score = score;
+                        best_moves.clear();
+                    }
+                    if score == best_score {
+                        best_moves.push(input);
+                    }
+                }
+                rng.choose(&best_moves).unwrap_or_default()
+            }
+        }
+    }
+}
+
+struct RunResult {
+    seed: u32,
+    ticks: u32,
+    swept: u32,
+    max_mess: usize,
+    level: usize,
+    loss: LossCondition,
+    puzzle: Option<PuzzleStatus>,
+}
+
+/// Play from the given board. The seed is the one of the bot, and of the
+/// board unless it is a puzzle.
+fn run(
+    seed: u32,
+    bot: Bot,
+    max_ticks: u32,
+    mut board: Board,
+    puzzle: Option<&Puzzle>,
+) -> RunResult {
+    // The bot has its own randomness, so that it does not change the spawns:
+    let mut bot_rng = GameRng::new(seed.wrapping_add(1));
+    let mut max_mess = 0;
+    let mut beat_counter = 0;
+    let mut status = puzzle.map(|x| x.goal.status(&board));
+    while board.tick < max_ticks
+        && board.loss_condition == LossCondition::NoLoss
+        && status.unwrap_or_default() == PuzzleStatus::Playing
+    {
+        // The bot plays once per beat:
+        let input = if beat_counter == 0 {
+            bot.next_input(&board, &mut bot_rng)
+        } else {
+            GameControl::Idle
+        };
+        beat_counter = (beat_counter + 1) % TIMER_SCALE_FACTOR;
+        board.step(input);
+        max_mess = max_mess.max(board.mess);
+        status = puzzle.map(|x| x.goal.status(&board));
+    }
+    RunResult {
+        seed,
+        ticks: board.tick,
+        swept: board.swept,
+        max_mess,
+        level: board.level,
+        loss: board.loss_condition,
+        puzzle: status,
+    }
+}
+
+fn arg<T: FromStr>(args: &[String], name: &str) -> Option<T> {
+    let ndx = args.iter().position(|x| x == name)?;
+    match args.get(ndx + 1).map(|x| x.parse()) {
+        Some(Ok(value)) => Some(value),
+        _ => {
+            eprintln!("invalid value for {}", name);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// A size written `<w>x<h>`
+fn size_arg(args: &[String], name: &str) -> Option<[u32; 2]> {
+    let value: String = arg(args, name)?;
+    let size = value
+        .split_once('x')
+        .and_then(|(w, h)| Some([w.parse().ok()?, h.parse().ok()?]));
+    if size.is_none() {
+        eprintln!("invalid value for {}", name);
+        std::process::exit(1);
+    }
+    size
+}
+
+fn load_ron<T: DeserializeOwned>(path: &str, what: &str) -> T {
+    let parsed = std::fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| ron::from_str(&text).map_err(|err| err.to_string()));
+    match parsed {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("could not load the {} {}: {}", what, path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let seed = arg(&args, "--seed").unwrap_or_else(GameRng::random_seed);
+    let runs: u32 = arg(&args, "--runs").unwrap_or(1);
+    let bot: Bot = arg(&args, "--bot").unwrap_or(Bot::Greedy);
+    let max_ticks = arg(&args, "--ticks").unwrap_or(DEFAULT_TICKS);
+    let beat_length: f32 = arg(&args, "--beat-length").unwrap_or(DEFAULT_BEAT_LENGTH);
+    let default_config = match arg::<String>(&args, "--difficulty").as_deref() {
+        None | Some("normal") => BoardConfig::default(),
+        Some("easy") => BoardConfig::easy(),
+        Some("hard") => BoardConfig::hard(),
+        Some(other) => {
+            eprintln!("unknown difficulty '{}'", other);
+            std::process::exit(1);
+        }
+    };
+    let config = BoardConfig {
+        spawn_frequency: arg(&args, "--spawn-frequency").unwrap_or(default_config.spawn_frequency),
+        push_cooldown: arg(&args, "--push-cooldown").unwrap_or(default_config.push_cooldown),
+        mess_limit: arg(&args, "--mess-limit").unwrap_or(default_config.mess_limit),
+        special_timeout: arg(&args, "--special-timeout").unwrap_or(default_config.special_timeout),
+    };
+    let default_geometry = BoardGeometry::default();
+    let geometry = match (
+        arg::<String>(&args, "--stage"),
+        size_arg(&args, "--board-size"),
+        size_arg(&args, "--stage-size"),
+    ) {
+        (Some(path), _, _) => {
+            let stage_map: StageMap = load_ron(&path, "stage");
+            match BoardGeometry::from_map(&stage_map.map) {
+                Ok(geometry) => geometry,
+                Err(err) => {
+                    eprintln!("invalid stage {}: {}", path, err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        (None, None, None) => default_geometry,
+        (None, board_size, stage_size) => {
+            let board_size = board_size.unwrap_or(default_geometry.size);
+            // The default stage leaves 2 tiles of sky on each side:
+            let stage_size = stage_size.unwrap_or(board_size.map(|x| x.saturating_sub(4)));
+            BoardGeometry::centred(board_size, stage_size)
+        }
+    };
+    if !geometry.is_valid() {
+        eprintln!(
+            "invalid board geometry: {:?} with a stage on {:?}-{:?}",
+            geometry.size, geometry.stage_bl, geometry.stage_ur
+        );
+        std::process::exit(1);
+    }
+    let beatmap: Option<Beatmap> =
+        arg::<String>(&args, "--beatmap").map(|path| load_ron(&path, "beatmap"));
+    let ramp: Option<Ramp> = arg::<String>(&args, "--ramp").map(|path| load_ron(&path, "ramp"));
+    let puzzle: Option<(Puzzle, _)> = arg::<String>(&args, "--puzzle").map(|path| {
+        let puzzle: Puzzle = load_ron(&path, "puzzle");
+        match puzzle.layout() {
+            Ok(layout) => (puzzle, layout),
+            Err(err) => {
+                eprintln!("invalid puzzle {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    });
+    let geometry = match &puzzle {
+        Some((_, layout)) => layout.geometry.clone(),
+        None => geometry,
+    };
+
+    println!(
+        "bot: {:?}, {:?}, board: {:?}, stage: {:?}-{:?}, beatmap: {}, ramp: {}, puzzle: {}",
+        bot,
+        config,
+        geometry.size,
+        geometry.stage_bl,
+        geometry.stage_ur,
+        beatmap.is_some(),
+        ramp.is_some(),
+        puzzle.as_ref().map(|x| x.0.name.as_str()).unwrap_or("none")
+    );
+    println!("seed\tticks\tbeats\ttime (s)\tswept\tmax mess\tlevel\tloss\tpuzzle");
+    let mut total_ticks = 0;
+    let mut total_swept = 0;
+    let mut solved = 0;
+    for ndx in 0..runs {
+        let run_seed = seed.wrapping_add(ndx);
+        let board = match &puzzle {
+            Some((_, layout)) => layout.board(config),
+            None => Board::with_config(run_seed, config)
+                .with_geometry(geometry.clone())
+                .with_beatmap(beatmap.clone())
+                .with_ramp(ramp.clone()),
+        };
+        let result = run(
+            run_seed,
+            bot,
+            max_ticks,
+            board,
+            puzzle.as_ref().map(|x| &x.0),
+        );
+        let beats = result.ticks / TIMER_SCALE_FACTOR as u32;
+        println!(
+            "{}\t{}\t{}\t{:.1}\t{}\t{}\t{}\t{:?}\t{}",
+            result.seed,
+            result.ticks,
+            beats,
+            beats as f32 * beat_length,
+            result.swept,
+            result.max_mess,
+            result.level,
+            result.loss,
+            result
+                .puzzle
+                .map(|x| format!("{:?}", x))
+                .unwrap_or_else(|| "-".to_string())
+        );
+        total_ticks += result.ticks as u64;
+        total_swept += result.swept as u64;
+        if result.puzzle == Some(PuzzleStatus::Solved) {
+            solved += 1;
+        }
+    }
+    if runs > 1 {
+        println!(
+            "mean: {:.1} ticks, {:.1} clouds swept",
+            total_ticks as f64 / runs as f64,
+            total_swept as f64 / runs as f64
+        );
+        if puzzle.is_some() {
+            println!("solved: {}/{}", solved, runs);
+        }
+    }
+}
@@ -0,0 +1,1533 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Game rules of Cloud Sweeper, independent from Bevy.
+//!
+//! The [`Board`] owns the grid, the clouds and the player. It is advanced one
+//! tick of the main clock at a time with [`Board::step`], which returns the
+//! [`BoardEvent`]s describing what happened. The ECS systems only render and
+//! play sounds according to these events.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+use crate::beatmap::{Beatmap, ChartBeat};
+use crate::ramp::{Ramp, RampLevel};
+
+// Geometry of the default board, see `BoardGeometry`:
+pub const LEVEL_SIZE: u32 = 10;
+pub const STAGE_BL: [u32; 2] = [2, 2];
+pub const STAGE_UR: [u32; 2] = [7, 7];
+pub const INIT_POS: [i8; 2] = [5i8, 5i8];
+
+// Multiple of the move timer:
+pub const SPAWN_FREQUENCY: u8 = 3;
+// Offset for delaying cloud spawning depending on the direction:
+pub const SPAWN_OFFSET: [u8; 4] = [0, 1, 0, 1];
+// We sync the actions of the player with the music
+pub const TIMER_SCALE_FACTOR: u8 = 4;
+pub const BEATS_PER_BAR: u32 = 4;
+pub const SEQUENCE: [CloudDir; 4] = [
+    CloudDir::Left,
+    CloudDir::Up,
+    CloudDir::Right,
+    CloudDir::Down,
+];
+pub const CLOUD_COUNT_LOSE_COND: usize = 16;
+// The push cooldown is a number of beats:
+pub const PUSH_COOLDOWN_FACTOR: u8 = 4;
+pub const SPECIAL_ACTIVATION_NB: u8 = 2;
+pub const SPECIAL_TIMEOUT: u8 = 4;
+// Offsets in `SEQUENCE` of the borders used by the extra spawns of the ramp,
+// the opposite border first:
+pub const EXTRA_SPAWN_OFFSETS: [usize; 3] = [2, 1, 3];
+
+#[derive(Default, Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum CloudDir {
+    #[default]
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+///Enum for the direction. Idle has been added to be able to use an array buffer
+/// instead of a vector.
+#[derive(Default, Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum GameControl {
+    #[default]
+    Idle,
+    Up,
+    Down,
+    Left,
+    Right,
+    Special,
+}
+
+#[derive(Default, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum PushState {
+    #[default]
+    Empty,
+    Blocked,
+    CanPush,
+    PlayerCanPush,
+    Despawn,
+    PushOver,
+    PlayerPushOver,
+}
+
+#[derive(Default, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum TileOccupation {
+    #[default]
+    Empty,
+    Player,
+    LeftCloud,
+    RightCloud,
+    UpCloud,
+    DownCloud,
+    Despawn,
+    CooldownCloud,
+}
+
+#[derive(Default, Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum LossCondition {
+    #[default]
+    NoLoss,
+    TooMessy,
+    Stuck,
+}
+
+/// A cloud as seen by the rules. The id is used by the ECS to find back the
+/// entity rendering it.
+#[derive(Debug, Copy, Clone)]
+pub struct BoardCloud {
+    pub id: u32,
+    pub pos: [i8; 2],
+    pub dir: CloudDir,
+    pub cooling: bool,
+    /// Beats the cloud still has to wait before moving again
+    pub cooldown: u8,
+}
+
+/// Rules which can be tuned, the defaults are the ones of the Normal
+/// difficulty
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoardConfig {
+    /// A cloud spawns every `spawn_frequency` turns of its direction
+    pub spawn_frequency: u8,
+    /// Cooldown of a pushed cloud, in beats
+    pub push_cooldown: u8,
+    /// The game is lost when there are more clouds than this on the stage
+    pub mess_limit: usize,
+    /// Ticks the player has to trigger the special once it has been started
+    pub special_timeout: u8,
+}
+
+impl Default for BoardConfig {
+    fn default() -> Self {
+        BoardConfig {
+            spawn_frequency: SPAWN_FREQUENCY,
+            push_cooldown: PUSH_COOLDOWN_FACTOR,
+            mess_limit: CLOUD_COUNT_LOSE_COND,
+            special_timeout: SPECIAL_TIMEOUT,
+        }
+    }
+}
+
+impl BoardConfig {
+    pub fn easy() -> Self {
+        BoardConfig {
+            spawn_frequency: SPAWN_FREQUENCY + 1,
+            mess_limit: CLOUD_COUNT_LOSE_COND + 4,
+            special_timeout: SPECIAL_TIMEOUT + 2,
+            ..Default::default()
+        }
+    }
+
+    pub fn hard() -> Self {
+        BoardConfig {
+            spawn_frequency: SPAWN_FREQUENCY - 1,
+            push_cooldown: 2 * PUSH_COOLDOWN_FACTOR,
+            special_timeout: SPECIAL_TIMEOUT - 1,
+            ..Default::default()
+        }
+    }
+}
+
+/// Everything that can happen on the board during a step
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BoardEvent {
+    /// `pushed` is true when the move is part of a push (the player pushing
+    /// a cloud or being pushed by one) rather than a free step
+    PlayerMoved {
+        from: [i8; 2],
+        to: [i8; 2],
+        pushed: bool,
+    },
+    /// The player pushed a cloud in the given direction
+    PlayerPush {
+        dir: CloudDir,
+    },
+    /// The special has been triggered and the adjacent clouds turned
+    Special,
+    CloudSpawned {
+        id: u32,
+        pos: [i8; 2],
+        dir: CloudDir,
+    },
+    /// `by_player` is true when the cloud has been pushed by the player,
+    /// rather than by another cloud or by itself
+    CloudMoved {
+        id: u32,
+        from: [i8; 2],
+        to: [i8; 2],
+        pushed: bool,
+        by_player: bool,
+    },
+    CloudCooldown {
+        id: u32,
+        cooling: bool,
+    },
+    CloudTurned {
+        id: u32,
+        dir: CloudDir,
+    },
+    /// The cloud reached the edge of the board by itself
+    CloudDespawned {
+        id: u32,
+    },
+    /// The cloud has been pushed out of the board or into a hole, `by_player`
+    /// as for `CloudMoved`
+    CloudPushedOff {
+        id: u32,
+        by_player: bool,
+    },
+    /// A new level of the ramp has been reached, starting from 1
+    LevelUp {
+        level: usize,
+    },
+    Lost(LossCondition),
+}
+
+/// Source of every random decision of the rules. Two boards created with the
+/// same seed and fed with the same inputs play exactly the same run.
+#[derive(Clone)]
+pub struct GameRng {
+    seed: u32,
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u32) -> Self {
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed as u64),
+        }
+    }
+
+    /// A fresh seed for when the player did not pick one
+    pub fn random_seed() -> u32 {
+        rand::random()
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn choose<T: Copy>(&mut self, items: &[T]) -> Option<T> {
+        items.choose(&mut self.rng).copied()
+    }
+}
+
+/// Kind of a tile of the board, see `BoardGeometry::from_map`
+#[derive(Default, Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum StageTile {
+    /// Only the clouds can go there, they spawn and leave the board on it
+    #[default]
+    Sky,
+    Platform,
+    /// The clouds falling into it vanish, the player cannot go there
+    Hole,
+    /// Blocks both the player and the clouds
+    Wall,
+}
+
+impl StageTile {
+    /// The character of the tile in a stage map
+    pub fn symbol(self) -> char {
+        match self {
+            StageTile::Sky => '~',
+            StageTile::Platform => '#',
+            StageTile::Hole => 'o',
+            StageTile::Wall => 'X',
+        }
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        [
+            StageTile::Sky,
+            StageTile::Platform,
+            StageTile::Hole,
+            StageTile::Wall,
+        ]
+        .into_iter()
+        .find(|x| x.symbol() == symbol)
+    }
+}
+
+// Platform tile where the player starts in a stage map:
+pub const INIT_POS_SYMBOL: char = '@';
+
+/// Content of a stage file, see [`BoardGeometry::from_map`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageMap {
+    pub map: Vec<String>,
+}
+
+/// Size of the board and shape of the stage on it. The tiles around the
+/// stage are sky, where the clouds spawn and leave the board.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BoardGeometry {
+    /// Number of columns and rows of the board
+    pub size: [u32; 2],
+    /// Bottom left tile of the smallest rectangle holding the stage
+    pub stage_bl: [u32; 2],
+    /// Upper right tile of the smallest rectangle holding the stage, included
+    pub stage_ur: [u32; 2],
+    /// Where the player starts, on the platform
+    pub init_pos: [i8; 2],
+    /// Indexed by column then row
+    pub tiles: Vec<Vec<StageTile>>,
+}
+
+impl Default for BoardGeometry {
+    fn default() -> Self {
+        BoardGeometry::centred(
+            [LEVEL_SIZE, LEVEL_SIZE],
+            [0, 1].map(|i| STAGE_UR[i] - STAGE_BL[i] + 1),
+        )
+    }
+}
+
+impl BoardGeometry {
+    /// A rectangular platform of `stage_size` tiles in the middle of the
+    /// board, the player starting on its centre
+    pub fn centred(size: [u32; 2], stage_size: [u32; 2]) -> Self {
+        let stage_bl = [0, 1].map(|i| size[i].saturating_sub(stage_size[i]) / 2);
+        let stage_ur = [0, 1].map(|i| stage_bl[i] + stage_size[i].max(1) - 1);
+        let tiles = (0..size[0])
+            .map(|x| {
+                (0..size[1])
+                    .map(|y| {
+                        if (stage_bl[0]..=stage_ur[0]).contains(&x)
+                            && (stage_bl[1]..=stage_ur[1]).contains(&y)
+                        {
+                            StageTile::Platform
+                        } else {
+                            StageTile::Sky
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        BoardGeometry {
+            size,
+            stage_bl,
+            stage_ur,
+            init_pos: [0, 1].map(|i| (stage_bl[i] + stage_ur[i]).div_ceil(2) as i8),
+            tiles,
+        }
+    }
+
+    /// Read a stage drawn with one string per row, from the top one to the
+    /// bottom one. Each character is the symbol of a `StageTile`, or
+    /// `INIT_POS_SYMBOL` for the platform tile where the player starts.
+    /// Without it, the player starts in the middle of the stage.
+    pub fn from_map(rows: &[String]) -> Result<Self, String> {
+        let width = rows.iter().map(|x| x.chars().count()).max().unwrap_or(0);
+        let height = rows.len();
+        let mut tiles = vec![vec![StageTile::Sky; height]; width];
+        let mut init_pos = None;
+        for (row_ndx, row) in rows.iter().enumerate() {
+            let y = height - 1 - row_ndx;
+            for (x, symbol) in row.chars().enumerate() {
+                tiles[x][y] = if symbol == INIT_POS_SYMBOL {
+                    init_pos = Some([x as i8, y as i8]);
+                    StageTile::Platform
+                } else {
+                    StageTile::from_symbol(symbol)
+                        .ok_or_else(|| format!("unknown tile '{}' in row {}", symbol, row_ndx))?
+                };
+            }
+        }
+        // The stage is everything which is not sky:
+        let stage_tiles: Vec<[u32; 2]> = (0..width)
+            .flat_map(|x| (0..height).map(move |y| [x, y]))
+            .filter(|&[x, y]| tiles[x][y] != StageTile::Sky)
+            .map(|[x, y]| [x as u32, y as u32])
+            .collect();
+        if stage_tiles.is_empty() {
+            return Err("the stage is empty".to_string());
+        }
+        let stage_bl = [0, 1].map(|i| stage_tiles.iter().map(|x| x[i]).min().unwrap_or(0));
+        let stage_ur = [0, 1].map(|i| stage_tiles.iter().map(|x| x[i]).max().unwrap_or(0));
+        let geometry = BoardGeometry {
+            size: [width as u32, height as u32],
+            stage_bl,
+            stage_ur,
+            init_pos: init_pos
+                .unwrap_or_else(|| [0, 1].map(|i| (stage_bl[i] + stage_ur[i]).div_ceil(2) as i8)),
+            tiles,
+        };
+        if geometry.is_valid() {
+            Ok(geometry)
+        } else {
+            Err(
+                "the stage must be surrounded by sky and the player start on the platform"
+                    .to_string(),
+            )
+        }
+    }
+
+    /// The stage must leave a border of sky around it for the clouds to
+    /// spawn, and the player must start on the platform
+    pub fn is_valid(&self) -> bool {
+        self.tiles.len() == self.size[0] as usize
+            && self.tiles.iter().all(|x| x.len() == self.size[1] as usize)
+            && (0..2).all(|i| {
+                self.size[i] <= i8::MAX as u32
+                    && 0 < self.stage_bl[i]
+                    && self.stage_bl[i] <= self.stage_ur[i]
+                    && self.stage_ur[i] + 1 < self.size[i]
+            })
+            && self.tile(self.init_pos) == StageTile::Platform
+    }
+
+    pub fn width(&self) -> u32 {
+        self.size[0]
+    }
+
+    pub fn height(&self) -> u32 {
+        self.size[1]
+    }
+
+    pub fn is_out_of_range(&self, tile: [i8; 2]) -> bool {
+        (0..2).any(|i| tile[i] < 0 || tile[i] >= self.size[i] as i8)
+    }
+
+    /// Kind of a tile, sky outside of the board
+    pub fn tile(&self, tile: [i8; 2]) -> StageTile {
+        if self.is_out_of_range(tile) {
+            StageTile::Sky
+        } else {
+            self.tiles[tile[0] as usize][tile[1] as usize]
+        }
+    }
+
+    pub fn is_sky(&self, tile: [i8; 2]) -> bool {
+        self.tile(tile) == StageTile::Sky
+    }
+
+    /// Whether the player swept a cloud off the stage: pushed it onto the
+    /// sky, into a hole or out of the board
+    pub fn is_sweep(&self, event: &BoardEvent) -> bool {
+        match *event {
+            BoardEvent::CloudMoved {
+                from,
+                to,
+                by_player: true,
+                ..
+            } => !self.is_sky(from) && self.is_sky(to),
+            BoardEvent::CloudPushedOff { by_player, .. } => by_player,
+            _ => false,
+        }
+    }
+
+    /// The tiles where the clouds going in the given direction spawn, on the
+    /// border of the board opposite to it and facing the stage
+    pub fn spawn_line(&self, border: CloudDir) -> Vec<[i8; 2]> {
+        let columns = self.stage_bl[0] as i8..=self.stage_ur[0] as i8;
+        let rows = self.stage_bl[1] as i8..=self.stage_ur[1] as i8;
+        let last = [self.size[0] as i8 - 1, self.size[1] as i8 - 1];
+        match border {
+            CloudDir::Down => columns.map(|x| [x, last[1]]).collect(),
+            CloudDir::Left => rows.map(|y| [last[0], y]).collect(),
+            CloudDir::Right => rows.map(|y| [0, y]).collect(),
+            CloudDir::Up => columns.map(|x| [x, 0]).collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GridState {
+    /// Indexed by column then row
+    pub grid: Vec<Vec<TileOccupation>>,
+    pub geometry: BoardGeometry,
+}
+
+impl Default for GridState {
+    fn default() -> Self {
+        GridState::new(BoardGeometry::default())
+    }
+}
+
+impl GridState {
+    pub fn new(geometry: BoardGeometry) -> Self {
+        let mut grid = vec![
+            vec![TileOccupation::Empty; geometry.height() as usize];
+            geometry.width() as usize
+        ];
+        grid[geometry.init_pos[0] as usize][geometry.init_pos[1] as usize] = TileOccupation::Player;
+        GridState { grid, geometry }
+    }
+
+    /// Check whether the next tile is occupied. Here the function is called on
+    /// the tile N+1 such that we check the tile N+2. Therefore we need the
+    /// tile being pushed and the direction in which the push is done:
+    pub fn is_occupied(&self, tile: [i8; 2], dir: CloudDir, object: TileOccupation) -> PushState {
+        if self.is_out_of_range(tile) {
+            return PushState::Despawn;
+        }
+
+        /* ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ Holes and walls ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ */
+        match self.geometry.tile(tile) {
+            StageTile::Wall => return PushState::Blocked,
+            StageTile::Hole if object == TileOccupation::Player => return PushState::Blocked,
+            // The cloud falls into the hole:
+            StageTile::Hole => return PushState::Despawn,
+            StageTile::Sky | StageTile::Platform => (),
+        }
+
+        /* ▓▓▓▓▓ Case where the player is close to the edge of the stage ▓▓▓▓ */
+        // To do before checking whether the cell is empty since we detect sky
+        // tiles as empty
+        if object == TileOccupation::Player && self.is_sky(tile) {
+            return PushState::Blocked;
+        }
+
+        let target_tile_occ = self.tile(tile);
+        // Nothing on the target tile, you are good to go:
+        if target_tile_occ == TileOccupation::Empty {
+            return PushState::Empty;
+        }
+
+        // Check the N+2 tile (behind the target tile):
+        let np2_tile = neighbour(tile, dir);
+
+        let np2_in_range = !self.is_out_of_range(np2_tile);
+        let np2_kind = self.geometry.tile(np2_tile);
+
+        // Here deal with the case where we are on the edge of the board, or
+        // in front of a hole. The player cannot be pushed over.
+        if !np2_in_range || np2_kind == StageTile::Hole {
+            if target_tile_occ == TileOccupation::Player {
+                return PushState::Blocked;
+            }
+            return match dir {
+                CloudDir::Down => match target_tile_occ {
+                    TileOccupation::UpCloud => PushState::Blocked,
+                    _ => PushState::PushOver,
+                },
+                CloudDir::Up => match target_tile_occ {
+                    TileOccupation::DownCloud => PushState::Blocked,
+                    _ => PushState::PushOver,
+                },
+                CloudDir::Left => match target_tile_occ {
+                    TileOccupation::RightCloud => PushState::Blocked,
+                    _ => PushState::PushOver,
+                },
+                CloudDir::Right => match target_tile_occ {
+                    TileOccupation::LeftCloud => PushState::Blocked,
+                    _ => PushState::PushOver,
+                },
+            };
+        }
+
+        let next_tile_occ = self.tile(np2_tile);
+        let tile_np2_occupied = np2_kind == StageTile::Wall
+            || !matches!(
+                next_tile_occ,
+                TileOccupation::Empty | TileOccupation::Despawn
+            );
+
+        // Case where there is something behind, just forget it
+        if tile_np2_occupied {
+            PushState::Blocked
+        } else {
+            // Case where the cloud is cooling down:
+            if matches!(target_tile_occ, TileOccupation::CooldownCloud) {
+                return PushState::Blocked;
+            }
+
+            // The player can only be pushed on the platform:
+            if target_tile_occ == TileOccupation::Player && np2_kind != StageTile::Platform {
+                return PushState::Blocked;
+            }
+
+            // case where the tile behind is empty, it depends on the target
+            // tile
+            match dir {
+                CloudDir::Down => match target_tile_occ {
+                    TileOccupation::UpCloud => PushState::Blocked,
+                    _ => PushState::CanPush,
+                },
+                CloudDir::Up => match target_tile_occ {
+                    TileOccupation::DownCloud => PushState::Blocked,
+                    _ => PushState::CanPush,
+                },
+                CloudDir::Left => match target_tile_occ {
+                    TileOccupation::RightCloud => PushState::Blocked,
+                    _ => PushState::CanPush,
+                },
+                CloudDir::Right => match target_tile_occ {
+                    TileOccupation::LeftCloud => PushState::Blocked,
+                    _ => PushState::CanPush,
+                },
+            }
+        }
+    }
+
+    pub fn is_out_of_range(&self, tile: [i8; 2]) -> bool {
+        self.geometry.is_out_of_range(tile)
+    }
+
+    pub fn is_sky(&self, tile: [i8; 2]) -> bool {
+        self.geometry.is_sky(tile)
+    }
+
+    /// Remove the entity from the previous tile and bring it to the new tile
+    fn move_on_grid(&mut self, source_tile: [i8; 2], target_tile: [i8; 2], object: TileOccupation) {
+        if self.is_out_of_range(target_tile) {
+            self.set_tile(source_tile, TileOccupation::Despawn);
+        } else {
+            self.set_tile(source_tile, TileOccupation::Empty);
+            self.set_tile(target_tile, object);
+        }
+    }
+
+    /// The tiles where the clouds going in the given direction spawn, on the
+    /// opposite border
+    pub fn spawn_line(&self, border: CloudDir) -> Vec<[i8; 2]> {
+        self.geometry.spawn_line(border)
+    }
+
+    /// Pick a free tile on the border opposite to the cloud direction and
+    /// occupy it with a new cloud
+    pub fn new_cloud(&mut self, border: CloudDir, rng: &mut GameRng) -> Option<[i8; 2]> {
+        let non_occupied: Vec<[i8; 2]> = self
+            .spawn_line(border)
+            .into_iter()
+            .filter(|v| self.is_occupied(*v, border, dir_to_tile(border)) == PushState::Empty)
+            .collect();
+        if let Some(pos) = rng.choose(&non_occupied) {
+            // Add the cloud to the grid
+            self.set_tile(pos, dir_to_tile(border));
+            Some(pos)
+        } else {
+            None
+        }
+    }
+
+    fn set_tile(&mut self, tile: [i8; 2], object: TileOccupation) {
+        self.grid[tile[0] as usize][tile[1] as usize] = object;
+    }
+
+    pub fn tile(&self, tile: [i8; 2]) -> TileOccupation {
+        self.grid[tile[0] as usize][tile[1] as usize]
+    }
+}
+
+/// The complete state of a run. One call to [`Board::step`] corresponds to one
+/// tick of the main clock, the clouds move every `TIMER_SCALE_FACTOR` ticks,
+/// following the beatmap when there is one.
+#[derive(Clone)]
+pub struct Board {
+    pub grid: GridState,
+    pub clouds: Vec<BoardCloud>,
+    pub player_pos: [i8; 2],
+    pub loss_condition: LossCondition,
+    /// Number of clouds on the stage, as shown by the mess bar
+    pub mess: usize,
+    pub tick: u32,
+    pub rng: GameRng,
+    pub config: BoardConfig,
+    pub beatmap: Option<Beatmap>,
+    pub ramp: Option<Ramp>,
+    /// Number of beats played since the start of the run
+    pub beat: u32,
+    /// Level of the ramp reached, 0 before the first one
+    pub level: usize,
+    /// Clouds the player swept off the stage since the start of the run, see
+    /// `BoardGeometry::is_sweep`
+    pub swept: u32,
+    /// Inputs played since the start of the run, idle ticks excluded
+    pub moves: u32,
+    /// Whether new clouds appear on the borders
+    pub spawning: bool,
+    /// Whether the run ends when the stage is too messy or the player stuck
+    pub can_lose: bool,
+    cloud_counter: u8,
+    special_control: u8,
+    special_timeout: u8,
+    cur_cloud: CloudDir,
+    spawn_counter: [u8; 4],
+    next_cloud_id: u32,
+    pushed_clouds: Vec<([i8; 2], CloudDir)>,
+    next_pushed_clouds: Vec<([i8; 2], CloudDir, PushState)>,
+}
+
+impl Board {
+    pub fn new(seed: u32) -> Self {
+        Board::with_config(seed, BoardConfig::default())
+    }
+
+    pub fn with_config(seed: u32, config: BoardConfig) -> Self {
+        Board {
+            grid: GridState::default(),
+            clouds: Vec::new(),
+            player_pos: INIT_POS,
+            loss_condition: LossCondition::NoLoss,
+            mess: 0,
+            tick: 0,
+            rng: GameRng::new(seed),
+            config,
+            beatmap: None,
+            ramp: None,
+            beat: 0,
+            level: 0,
+            swept: 0,
+            moves: 0,
+            spawning: true,
+            can_lose: true,
+            cloud_counter: 0,
+            special_control: 0,
+            special_timeout: 0,
+            cur_cloud: CloudDir::Left,
+            spawn_counter: SPAWN_OFFSET,
+            next_cloud_id: 0,
+            pushed_clouds: Vec::new(),
+            next_pushed_clouds: Vec::new(),
+        }
+    }
+
+    /// Play on another board than the default one, before the first step
+    pub fn with_geometry(mut self, geometry: BoardGeometry) -> Self {
+        self.player_pos = geometry.init_pos;
+        self.grid = GridState::new(geometry);
+        self
+    }
+
+    pub fn geometry(&self) -> &BoardGeometry {
+        &self.grid.geometry
+    }
+
+    /// Start with clouds already on the board, before the first step. They
+    /// must be on free tiles of the stage.
+    pub fn with_clouds(mut self, clouds: &[([i8; 2], CloudDir)]) -> Self {
+        for &(pos, dir) in clouds {
+            self.grid.set_tile(pos, dir_to_tile(dir));
+            self.clouds.push(BoardCloud {
+                id: self.next_cloud_id,
+                pos,
+                dir,
+                cooling: false,
+                cooldown: 0,
+            });
+            self.next_cloud_id += 1;
+        }
+        self.mess = self.count_clouds();
+        self
+    }
+
+    /// No cloud spawns, only the ones already on the board move
+    pub fn without_spawns(mut self) -> Self {
+        self.spawning = false;
+        self
+    }
+
+    /// The run goes on whatever happens on the stage
+    pub fn without_loss(mut self) -> Self {
+        self.can_lose = false;
+        self
+    }
+
+    /// Spawn and move the clouds according to a chart instead of the
+    /// direction sequence
+    pub fn with_beatmap(mut self, beatmap: Option<Beatmap>) -> Self {
+        self.beatmap = beatmap;
+        self
+    }
+
+    /// Make the rules harder as the run goes on
+    pub fn with_ramp(mut self, ramp: Option<Ramp>) -> Self {
+        self.ramp = ramp;
+        self
+    }
+
+    /// The level of the ramp currently applied
+    pub fn ramp_level(&self) -> Option<&RampLevel> {
+        let ramp = self.ramp.as_ref()?;
+        self.level
+            .checked_sub(1)
+            .and_then(|ndx| ramp.levels.get(ndx))
+    }
+
+    /// The rules of the difficulty, changed by the level of the ramp
+    pub fn rules(&self) -> BoardConfig {
+        match self.ramp_level() {
+            Some(level) => level.apply(&self.config),
+            None => self.config,
+        }
+    }
+
+    /// Advance the board by one tick, applying the player input first
+    pub fn step(&mut self, input: GameControl) -> Vec<BoardEvent> {
+        let mut events = Vec::new();
+        if self.loss_condition != LossCondition::NoLoss {
+            return events;
+        }
+        self.tick += 1;
+
+        self.apply_input(input, &mut events);
+        self.play_special(&mut events);
+        self.cloud_counter += 1;
+        if self.cloud_counter >= TIMER_SCALE_FACTOR {
+            self.cloud_counter = 0;
+            self.cool_down_clouds(&mut events);
+            let chart_beat = self
+                .beatmap
+                .as_ref()
+                .and_then(|x| x.beat(self.beat))
+                .cloned();
+            match chart_beat {
+                Some(chart_beat) => self.play_chart_beat(&chart_beat, &mut events),
+                None => self.play_sequence_beat(&mut events),
+            }
+            self.beat += 1;
+        }
+        self.push_clouds(&mut events);
+        self.despawn_clouds(&mut events);
+        self.mess = self.count_clouds();
+        self.update_level(&mut events);
+        self.check_loss_condition(&mut events);
+        events
+    }
+
+    fn update_level(&mut self, events: &mut Vec<BoardEvent>) {
+        let Some(ramp) = &self.ramp else {
+            return;
+        };
+        let level = ramp.level(self.beat / BEATS_PER_BAR, self.swept);
+        if level > self.level {
+            self.level = level;
+            events.push(BoardEvent::LevelUp { level });
+        }
+    }
+
+    /// Whether no cloud can move by itself anymore: each one is blocked and
+    /// none is cooling down. Without spawns, only the player can then change
+    /// the board.
+    pub fn is_settled(&self) -> bool {
+        self.clouds.iter().all(|cloud| {
+            !cloud.cooling
+                && self.grid.is_occupied(
+                    neighbour(cloud.pos, cloud.dir),
+                    cloud.dir,
+                    dir_to_tile(cloud.dir),
+                ) == PushState::Blocked
+        })
+    }
+
+    /// The four tiles around the player, in the order of `SEQUENCE`
+    pub fn player_neighbours(&self) -> [[i8; 2]; 4] {
+        SEQUENCE.map(|dir| neighbour(self.player_pos, dir))
+    }
+
+    /// Default rules: one direction moves per beat, and spawns every
+    /// `spawn_frequency` turns. The ramp can add spawns on other borders.
+    fn play_sequence_beat(&mut self, events: &mut Vec<BoardEvent>) {
+        let cloud_dir = self.next_cloud_direction();
+        self.move_clouds(cloud_dir, events);
+
+        let ndx = dir_index(cloud_dir);
+        let spawn_frequency = self.rules().spawn_frequency.max(1);
+        // The counter can be above a frequency lowered by the ramp:
+        self.spawn_counter[ndx] = (self.spawn_counter[ndx] + 1) % spawn_frequency;
+        if self.spawn_counter[ndx] == 0 {
+            self.new_cloud(cloud_dir, events);
+            let extra_spawns = self.ramp_level().map(|x| x.extra_spawns).unwrap_or(0);
+            for offset in EXTRA_SPAWN_OFFSETS.iter().take(extra_spawns as usize) {
+                self.new_cloud(SEQUENCE[(ndx + offset) % SEQUENCE.len()], events);
+            }
+        }
+    }
+
+    /// The clouds move first so that the new ones stay on their border for a
+    /// beat, like with the sequence
+    fn play_chart_beat(&mut self, chart_beat: &ChartBeat, events: &mut Vec<BoardEvent>) {
+        for cloud_dir in chart_beat.moves.iter() {
+            self.move_clouds(*cloud_dir, events);
+        }
+        for border in chart_beat.spawns.iter() {
+            self.new_cloud(*border, events);
+        }
+    }
+
+    fn next_cloud_direction(&mut self) -> CloudDir {
+        let next_cloud = SEQUENCE[(dir_index(self.cur_cloud) + 1) % SEQUENCE.len()];
+        self.cur_cloud = next_cloud;
+        next_cloud
+    }
+
+    fn apply_input(&mut self, input: GameControl, events: &mut Vec<BoardEvent>) {
+        if input != GameControl::Idle {
+            self.moves += 1;
+        }
+        let pl_pos = self.player_pos;
+        let (player_new_pos, action_direction, push_state): ([i8; 2], CloudDir, PushState) =
+            match input {
+                GameControl::Down => {
+                    let new_pos = if pl_pos[1] > (self.geometry().stage_bl[1] as i8) {
+                        [pl_pos[0], pl_pos[1] - 1]
+                    } else {
+                        pl_pos
+                    };
+                    let dir = CloudDir::Down;
+                    (
+                        new_pos,
+                        dir,
+                        self.grid.is_occupied(new_pos, dir, TileOccupation::Player),
+                    )
+                }
+                GameControl::Up => {
+                    let new_pos = if pl_pos[1] < (self.geometry().stage_ur[1] as i8) {
+                        [pl_pos[0], pl_pos[1] + 1]
+                    } else {
+                        pl_pos
+                    };
+                    let dir = CloudDir::Up;
+                    (
+                        new_pos,
+                        dir,
+                        self.grid.is_occupied(new_pos, dir, TileOccupation::Player),
+                    )
+                }
+                GameControl::Left => {
+                    let new_pos = if pl_pos[0] > (self.geometry().stage_bl[0] as i8) {
+                        [pl_pos[0] - 1, pl_pos[1]]
+                    } else {
+                        pl_pos
+                    };
+                    let dir = CloudDir::Left;
+                    (
+                        new_pos,
+                        dir,
+                        self.grid.is_occupied(new_pos, dir, TileOccupation::Player),
+                    )
+                }
+                GameControl::Right => {
+                    let new_pos = if pl_pos[0] < (self.geometry().stage_ur[0] as i8) {
+                        [pl_pos[0] + 1, pl_pos[1]]
+                    } else {
+                        pl_pos
+                    };
+                    let dir = CloudDir::Right;
+                    (
+                        new_pos,
+                        dir,
+                        self.grid.is_occupied(new_pos, dir, TileOccupation::Player),
+                    )
+                }
+                GameControl::Idle => (pl_pos, CloudDir::Right, PushState::Empty),
+                GameControl::Special => {
+                    self.special_control += 1;
+                    self.special_timeout = 0;
+                    (pl_pos, CloudDir::Down, PushState::Blocked)
+                }
+            };
+
+        if matches!(
+            input,
+            GameControl::Up | GameControl::Down | GameControl::Left | GameControl::Right
+        ) {
+            // reset the special buffer every time a direction is played:
+            self.special_control = 0;
+            match push_state {
+                PushState::Empty => {
+                    self.player_pos = player_new_pos;
+                    self.grid.set_tile(pl_pos, TileOccupation::Empty);
+                    self.grid.set_tile(player_new_pos, TileOccupation::Player);
+                    events.push(BoardEvent::PlayerMoved {
+                        from: pl_pos,
+                        to: player_new_pos,
+                        pushed: false,
+                    });
+                }
+                PushState::CanPush | PushState::PushOver => {
+                    // With a hole behind it, the cloud falls into it:
+                    let push_type = if push_state == PushState::PushOver {
+                        PushState::PlayerPushOver
+                    } else {
+                        PushState::PlayerCanPush
+                    };
+                    self.pushed_clouds.push((pl_pos, action_direction));
+                    self.next_pushed_clouds
+                        .push((player_new_pos, action_direction, push_type));
+                    events.push(BoardEvent::PlayerPush {
+                        dir: action_direction,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        self.special_timeout += 1;
+        // if the special is not used soon enough, it expires:
+        if self.special_timeout >= self.config.special_timeout {
+            self.special_timeout = 0;
+            self.special_control = 0;
+        }
+    }
+
+    /// Apply the special action: the clouds around the player turn to face
+    /// it
+    fn play_special(&mut self, events: &mut Vec<BoardEvent>) {
+        if self.special_control < SPECIAL_ACTIVATION_NB {
+            return;
+        }
+        events.push(BoardEvent::Special);
+        self.special_timeout = 0;
+
+        let pl_pos = self.player_pos;
+        let adj_clouds = [
+            ([pl_pos[0] - 1, pl_pos[1]], CloudDir::Right),
+            ([pl_pos[0] + 1, pl_pos[1]], CloudDir::Left),
+            ([pl_pos[0], pl_pos[1] - 1], CloudDir::Up),
+            ([pl_pos[0], pl_pos[1] + 1], CloudDir::Down),
+        ];
+
+        for cloud in self.clouds.iter_mut() {
+            if let Some((_, dir)) = adj_clouds.iter().find(|x| x.0 == cloud.pos) {
+                self.grid.set_tile(cloud.pos, dir_to_tile(*dir));
+                cloud.dir = *dir;
+                events.push(BoardEvent::CloudTurned {
+                    id: cloud.id,
+                    dir: *dir,
+                });
+            }
+        }
+        // Reset the counter
+        self.special_control = 0;
+    }
+
+    /// Count down the cooldown of the pushed clouds, once per beat. A cloud
+    /// released on a beat can move on that same beat.
+    fn cool_down_clouds(&mut self, events: &mut Vec<BoardEvent>) {
+        for cloud in self.clouds.iter_mut().filter(|x| x.cooling) {
+            cloud.cooldown = cloud.cooldown.saturating_sub(1);
+            if cloud.cooldown > 0 {
+                continue;
+            }
+            cloud.cooling = false;
+            self.grid.set_tile(cloud.pos, dir_to_tile(cloud.dir));
+            events.push(BoardEvent::CloudCooldown {
+                id: cloud.id,
+                cooling: false,
+            });
+        }
+    }
+
+    fn move_clouds(&mut self, cloud_dir: CloudDir, events: &mut Vec<BoardEvent>) {
+        for cloud in self.clouds.iter_mut().filter(|x| x.dir == cloud_dir) {
+            if cloud.cooling {
+                continue;
+            }
+            let next_tile = neighbour(cloud.pos, cloud_dir);
+            match self
+                .grid
+                .is_occupied(next_tile, cloud_dir, dir_to_tile(cloud_dir))
+            {
+                PushState::Blocked => {
+                    continue;
+                }
+                PushState::Despawn => {
+                    self.grid.set_tile(cloud.pos, TileOccupation::Despawn);
+                }
+                PushState::Empty => {
+                    self.grid
+                        .move_on_grid(cloud.pos, next_tile, dir_to_tile(cloud_dir));
+                    events.push(BoardEvent::CloudMoved {
+                        id: cloud.id,
+                        from: cloud.pos,
+                        to: next_tile,
+                        pushed: false,
+                        by_player: false,
+                    });
+                    cloud.pos = next_tile;
+                }
+                push_state => {
+                    self.pushed_clouds.push((cloud.pos, cloud_dir));
+                    self.next_pushed_clouds
+                        .push((next_tile, cloud_dir, push_state));
+                }
+            }
+        }
+    }
+
+    fn new_cloud(&mut self, border: CloudDir, events: &mut Vec<BoardEvent>) {
+        if !self.spawning {
+            return;
+        }
+        if let Some(pos) = self.grid.new_cloud(border, &mut self.rng) {
+            let id = self.next_cloud_id;
+            self.next_cloud_id += 1;
+            self.clouds.push(BoardCloud {
+                id,
+                pos,
+                dir: border,
+                cooling: false,
+                cooldown: 0,
+            });
+            events.push(BoardEvent::CloudSpawned {
+                id,
+                pos,
+                dir: border,
+            });
+        }
+    }
+
+    fn push_player(&mut self, dir: CloudDir, events: &mut Vec<BoardEvent>) {
+        let from = self.player_pos;
+        let to = neighbour(from, dir);
+        self.player_pos = to;
+        self.grid.set_tile(from, TileOccupation::Empty);
+        self.grid.set_tile(to, TileOccupation::Player);
+        events.push(BoardEvent::PlayerMoved {
+            from,
+            to,
+            pushed: true,
+        });
+    }
+
+    /// Deal with the cloud which need to be pushed. At this stage, one already
+    /// knows that the tile N+2 is empty to push the cloud
+    fn push_clouds(&mut self, events: &mut Vec<BoardEvent>) {
+        /* ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ Move first the next cloud "pushed": ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ */
+        let push_cooldown = self.rules().push_cooldown.max(1);
+        let next_pushed_clouds: Vec<_> = self.next_pushed_clouds.drain(..).collect();
+        for (pos, dir, push_type) in next_pushed_clouds {
+            // First push the player:
+            if self.player_pos == pos {
+                self.push_player(dir, events);
+                continue;
+            }
+
+            // Then push the clouds:
+            let mut pushed_off = Vec::new();
+            for cloud in self.clouds.iter_mut().filter(|x| x.pos == pos) {
+                // If cloud to be pushed out of the board, remove it instantly:
+                if matches!(push_type, PushState::PushOver | PushState::PlayerPushOver) {
+                    self.grid.set_tile(cloud.pos, TileOccupation::Empty);
+                    pushed_off.push(cloud.id);
+                    continue;
+                }
+
+                let target = neighbour(cloud.pos, dir);
+                let object = if push_type == PushState::PlayerCanPush {
+                    cloud.cooling = true;
+                    cloud.cooldown = push_cooldown;
+                    events.push(BoardEvent::CloudCooldown {
+                        id: cloud.id,
+                        cooling: true,
+                    });
+                    TileOccupation::CooldownCloud
+                } else {
+                    dir_to_tile(cloud.dir)
+                };
+                self.grid.move_on_grid(cloud.pos, target, object);
+                let event = BoardEvent::CloudMoved {
+                    id: cloud.id,
+                    from: cloud.pos,
+                    to: target,
+                    pushed: true,
+                    by_player: push_type == PushState::PlayerCanPush,
+                };
+                if self.grid.geometry.is_sweep(&event) {
+                    self.swept += 1;
+                }
+                events.push(event);
+                cloud.pos = target;
+            }
+            for id in pushed_off {
+                self.clouds.retain(|x| x.id != id);
+                let event = BoardEvent::CloudPushedOff {
+                    id,
+                    by_player: push_type == PushState::PlayerPushOver,
+                };
+                if self.grid.geometry.is_sweep(&event) {
+                    self.swept += 1;
+                }
+                events.push(event);
+            }
+        }
+
+        /* ▓▓▓▓▓▓▓▓▓ Then move the actual clouds pushing the other one: ▓▓▓▓▓▓▓▓▓ */
+        let pushed_clouds: Vec<_> = self.pushed_clouds.drain(..).collect();
+        for (pos, dir) in pushed_clouds {
+            // First move the player:
+            if self.player_pos == pos {
+                self.push_player(dir, events);
+                continue;
+            }
+
+            for cloud in self.clouds.iter_mut().filter(|x| x.pos == pos) {
+                let target = neighbour(cloud.pos, dir);
+                self.grid.move_on_grid(cloud.pos, target, dir_to_tile(dir));
+                events.push(BoardEvent::CloudMoved {
+                    id: cloud.id,
+                    from: cloud.pos,
+                    to: target,
+                    pushed: false,
+                    by_player: false,
+                });
+                cloud.pos = target;
+            }
+        }
+    }
+
+    /// Remove the clouds which left the board by themselves
+    fn despawn_clouds(&mut self, events: &mut Vec<BoardEvent>) {
+        let grid = &mut self.grid;
+        self.clouds.retain(|cloud| {
+            let out_of_range = grid.is_out_of_range(cloud.pos);
+            if out_of_range || grid.tile(cloud.pos) == TileOccupation::Despawn {
+                if !out_of_range {
+                    grid.set_tile(cloud.pos, TileOccupation::Empty);
+                }
+                events.push(BoardEvent::CloudDespawned { id: cloud.id });
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Number of clouds on the stage, cooling down or not
+    fn count_clouds(&self) -> usize {
+        let mut tmp_counter: usize = 0;
+        for i in 0..self.grid.grid.len() {
+            for j in 0..self.grid.grid[i].len() {
+                let is_stage = !self.grid.is_sky([i as i8, j as i8]);
+                let is_cloud = matches!(
+                    self.grid.grid[i][j],
+                    TileOccupation::LeftCloud
+                        | TileOccupation::RightCloud
+                        | TileOccupation::UpCloud
+                        | TileOccupation::DownCloud
+                        | TileOccupation::CooldownCloud
+                );
+                if is_stage && is_cloud {
+                    tmp_counter += 1;
+                }
+            }
+        }
+        tmp_counter
+    }
+
+    /// The player loses when the stage is too messy or when it cannot move at
+    /// all
+    fn check_loss_condition(&mut self, events: &mut Vec<BoardEvent>) {
+        if !self.can_lose {
+            return;
+        }
+        if self.mess > self.config.mess_limit {
+            self.loss_condition = LossCondition::TooMessy;
+        } else if self
+            .player_neighbours()
+            .into_iter()
+            .zip(SEQUENCE)
+            .all(|(tile, dir)| {
+                self.grid.is_occupied(tile, dir, TileOccupation::Player) == PushState::Blocked
+            })
+        {
+            self.loss_condition = LossCondition::Stuck;
+        } else {
+            return;
+        }
+        events.push(BoardEvent::Lost(self.loss_condition));
+    }
+}
+
+/// The tile next to `tile` in the direction `dir`
+pub fn neighbour(tile: [i8; 2], dir: CloudDir) -> [i8; 2] {
+    match dir {
+        CloudDir::Down => [tile[0], tile[1] - 1],
+        CloudDir::Up => [tile[0], tile[1] + 1],
+        CloudDir::Left => [tile[0] - 1, tile[1]],
+        CloudDir::Right => [tile[0] + 1, tile[1]],
+    }
+}
+
+pub fn dir_index(cloud_dir: CloudDir) -> usize {
+    SEQUENCE.iter().position(|&x| x == cloud_dir).unwrap()
+}
+
+/// Transform a cloud direction into a TileOccupation enum
+pub fn dir_to_tile(dir: CloudDir) -> TileOccupation {
+    match dir {
+        CloudDir::Down => TileOccupation::DownCloud,
+        CloudDir::Up => TileOccupation::UpCloud,
+        CloudDir::Left => TileOccupation::LeftCloud,
+        CloudDir::Right => TileOccupation::RightCloud,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default board without spawns, the player on [5, 5] in the middle
+    /// of the stage, which goes from [2, 2] to [7, 7]
+    fn board(clouds: &[([i8; 2], CloudDir)]) -> Board {
+        Board::new(0).without_spawns().with_clouds(clouds)
+    }
+
+    fn cloud_at(board: &Board, pos: [i8; 2]) -> &BoardCloud {
+        board.clouds.iter().find(|x| x.pos == pos).unwrap()
+    }
+
+    #[test]
+    fn player_pushes_cloud() {
+        let mut board = board(&[([6, 5], CloudDir::Up)]);
+        let events = board.step(GameControl::Right);
+        assert!(events.contains(&BoardEvent::PlayerPush {
+            dir: CloudDir::Right
+        }));
+        assert!(events.contains(&BoardEvent::CloudMoved {
+            id: 0,
+            from: [6, 5],
+            to: [7, 5],
+            pushed: true,
+            by_player: true,
+        }));
+        assert_eq!(board.player_pos, [6, 5]);
+        assert!(cloud_at(&board, [7, 5]).cooling);
+        assert_eq!(board.grid.tile([7, 5]), TileOccupation::CooldownCloud);
+        assert_eq!(board.grid.tile([6, 5]), TileOccupation::Player);
+    }
+
+    #[test]
+    fn push_is_blocked_by_occupied_tile_behind() {
+        let mut board = board(&[([6, 5], CloudDir::Up), ([7, 5], CloudDir::Up)]);
+        let events = board.step(GameControl::Right);
+        assert!(events.is_empty());
+        assert_eq!(board.player_pos, [5, 5]);
+        assert_eq!(board.grid.tile([6, 5]), TileOccupation::UpCloud);
+        assert_eq!(board.grid.tile([7, 5]), TileOccupation::UpCloud);
+    }
+
+    #[test]
+    fn cloud_pushes_another_off_the_board() {
+        // The up clouds move on the first beat, the fourth tick:
+        let mut board = board(&[([5, 8], CloudDir::Up), ([5, 9], CloudDir::Left)]);
+        let events: Vec<_> = (0..TIMER_SCALE_FACTOR)
+            .flat_map(|_| board.step(GameControl::Idle))
+            .collect();
+        assert!(events.contains(&BoardEvent::CloudPushedOff {
+            id: 1,
+            by_player: false,
+        }));
+        // Only the player sweeps:
+        assert_eq!(board.swept, 0);
+        assert_eq!(board.clouds.len(), 1);
+        assert_eq!(board.clouds[0].pos, [5, 9]);
+        assert_eq!(board.grid.tile([5, 9]), TileOccupation::UpCloud);
+    }
+
+    #[test]
+    fn sweeping_a_cloud_reaches_the_swept_level() {
+        let mut board = board(&[([7, 5], CloudDir::Down)]).with_ramp(Some(Ramp {
+            levels: vec![RampLevel {
+                swept: Some(1),
+                ..Default::default()
+            }],
+        }));
+        let events = board.step(GameControl::Right);
+        assert!(!events.contains(&BoardEvent::LevelUp { level: 1 }));
+        // From the edge of the stage onto the sky:
+        let events = board.step(GameControl::Right);
+        assert!(events.contains(&BoardEvent::CloudMoved {
+            id: 0,
+            from: [7, 5],
+            to: [8, 5],
+            pushed: true,
+            by_player: true,
+        }));
+        assert_eq!(board.swept, 1);
+        assert!(events.contains(&BoardEvent::LevelUp { level: 1 }));
+        assert_eq!(board.level, 1);
+    }
+
+    #[test]
+    fn player_pushes_cloud_into_a_hole() {
+        let map = [
+            "~~~~~~~", "~~~~~~~", "~~###~~", "~~@#o~~", "~~###~~", "~~~~~~~", "~~~~~~~",
+        ];
+        let geometry = BoardGeometry::from_map(&map.map(|x| x.to_string())).expect("valid stage");
+        let mut board = Board::new(0)
+            .with_geometry(geometry)
+            .without_spawns()
+            .with_clouds(&[([3, 3], CloudDir::Down)]);
+        let events = board.step(GameControl::Right);
+        assert!(events.contains(&BoardEvent::CloudPushedOff {
+            id: 0,
+            by_player: true,
+        }));
+        assert!(board.clouds.is_empty());
+        assert_eq!(board.swept, 1);
+        assert_eq!(board.player_pos, [3, 3]);
+        assert_eq!(board.grid.tile([3, 3]), TileOccupation::Player);
+        assert_eq!(board.grid.tile([4, 3]), TileOccupation::Empty);
+    }
+
+    /// Steps the cloud pushed right by the player to `[7, 5]` cools down
+    fn pushed_cloud_cooldown_steps(board: &mut Board) -> u32 {
+        board.step(GameControl::Right);
+        let mut steps = 0;
+        while cloud_at(board, [7, 5]).cooling {
+            board.step(GameControl::Idle);
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn pushed_cloud_cools_down_then_moves() {
+        let mut board = board(&[([6, 5], CloudDir::Up)]);
+        board.step(GameControl::Right);
+        assert_eq!(cloud_at(&board, [7, 5]).cooldown, PUSH_COOLDOWN_FACTOR);
+        // One beat per turn of the sequence, the last one on the left beat:
+        let mut events = Vec::new();
+        for _ in 1..PUSH_COOLDOWN_FACTOR as u32 * TIMER_SCALE_FACTOR as u32 {
+            events.extend(board.step(GameControl::Idle));
+        }
+        assert!(events.contains(&BoardEvent::CloudCooldown {
+            id: 0,
+            cooling: false,
+        }));
+        let cloud = cloud_at(&board, [7, 5]);
+        assert!(!cloud.cooling);
+        assert_eq!(board.grid.tile([7, 5]), TileOccupation::UpCloud);
+        // Then it waits for the next up beat:
+        for _ in 0..TIMER_SCALE_FACTOR {
+            board.step(GameControl::Idle);
+        }
+        assert_eq!(board.grid.tile([7, 5]), TileOccupation::Empty);
+        assert_eq!(board.grid.tile([7, 6]), TileOccupation::UpCloud);
+    }
+
+    #[test]
+    fn ramp_lengthens_the_push_cooldown() {
+        let clouds = [([6, 5], CloudDir::Up)];
+        let mut normal = board(&clouds);
+        let mut ramped = board(&clouds).with_ramp(Some(Ramp {
+            levels: vec![RampLevel {
+                bars: Some(0),
+                cooldown_lengthening: 2,
+                ..Default::default()
+            }],
+        }));
+        // The level is reached at the end of the first step:
+        normal.step(GameControl::Idle);
+        ramped.step(GameControl::Idle);
+        assert_eq!(ramped.rules().push_cooldown, PUSH_COOLDOWN_FACTOR + 2);
+        assert!(
+            pushed_cloud_cooldown_steps(&mut ramped) > pushed_cloud_cooldown_steps(&mut normal)
+        );
+    }
+
+    #[test]
+    fn mess_counts_every_cloud_on_the_stage() {
+        let mut board = board(&[
+            ([2, 2], CloudDir::Left),
+            ([3, 2], CloudDir::Right),
+            ([4, 2], CloudDir::Up),
+            ([5, 2], CloudDir::Down),
+            ([6, 5], CloudDir::Up),
+            // On the sky, out of the stage:
+            ([0, 5], CloudDir::Right),
+        ]);
+        assert_eq!(board.mess, 5);
+        // A cloud cooling down is still on the stage:
+        board.step(GameControl::Right);
+        assert_eq!(board.grid.tile([7, 5]), TileOccupation::CooldownCloud);
+        assert_eq!(board.mess, 5);
+    }
+
+    #[test]
+    fn special_turns_adjacent_clouds() {
+        let mut board = board(&[([4, 5], CloudDir::Up), ([5, 6], CloudDir::Left)]);
+        assert!(!board
+            .step(GameControl::Special)
+            .contains(&BoardEvent::Special));
+        let events = board.step(GameControl::Special);
+        assert!(events.contains(&BoardEvent::Special));
+        assert!(events.contains(&BoardEvent::CloudTurned {
+            id: 0,
+            dir: CloudDir::Right,
+        }));
+        assert!(events.contains(&BoardEvent::CloudTurned {
+            id: 1,
+            dir: CloudDir::Down,
+        }));
+        assert_eq!(board.grid.tile([4, 5]), TileOccupation::RightCloud);
+        assert_eq!(board.grid.tile([5, 6]), TileOccupation::DownCloud);
+    }
+
+    /// Four clouds around the player, each one facing it
+    const SURROUNDING_CLOUDS: [([i8; 2], CloudDir); 4] = [
+        ([4, 5], CloudDir::Right),
+        ([6, 5], CloudDir::Left),
+        ([5, 4], CloudDir::Up),
+        ([5, 6], CloudDir::Down),
+    ];
+
+    #[test]
+    fn no_loss_while_the_player_can_move() {
+        let mut board = board(&SURROUNDING_CLOUDS[..3]);
+        board.step(GameControl::Idle);
+        assert_eq!(board.loss_condition, LossCondition::NoLoss);
+    }
+
+    #[test]
+    fn loss_when_too_messy() {
+        let config = BoardConfig {
+            mess_limit: 1,
+            ..Default::default()
+        };
+        let mut board = Board::with_config(0, config)
+            .without_spawns()
+            .with_clouds(&[([2, 2], CloudDir::Up), ([7, 7], CloudDir::Right)]);
+        let events = board.step(GameControl::Idle);
+        assert_eq!(board.loss_condition, LossCondition::TooMessy);
+        assert!(events.contains(&BoardEvent::Lost(LossCondition::TooMessy)));
+    }
+
+    #[test]
+    fn loss_when_stuck() {
+        let mut board = board(&SURROUNDING_CLOUDS);
+        let events = board.step(GameControl::Idle);
+        assert_eq!(board.loss_condition, LossCondition::Stuck);
+        assert!(events.contains(&BoardEvent::Lost(LossCondition::Stuck)));
+        // The board does not change anymore:
+        assert!(board.step(GameControl::Up).is_empty());
+    }
+
+    #[test]
+    fn no_loss_without_loss_condition() {
+        let mut board = board(&SURROUNDING_CLOUDS).without_loss();
+        board.step(GameControl::Idle);
+        assert_eq!(board.loss_condition, LossCondition::NoLoss);
+    }
+}
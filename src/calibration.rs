@@ -0,0 +1,185 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Latency calibration, reached from the menu. A metronome beeps and the
+//! player taps any key or gamepad button along with it: the average delay
+//! between the beeps and the taps covers both the audio output and the input
+//! latency of the machine. Once saved as the input offset of the
+//! [`Settings`], the [`MainClock`](crate::logic::MainClock) takes it off the
+//! inputs when judging whether they are on the beat.
+
+use std::collections::VecDeque;
+
+use crate::audio::UiChannel;
+use crate::loading::{AudioAssets, FontAssets};
+use crate::menu::{despawn_screen, spawn_menu_button, BackButton};
+use crate::settings::{save_settings, Settings};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy_kira_audio::prelude::*;
+
+// Time between two beeps of the metronome, in seconds:
+pub const METRONOME_PERIOD: f32 = 0.5;
+// Number of taps averaged, and needed before the offset can be saved:
+pub const CALIBRATION_TAPS: usize = 16;
+pub const CALIBRATION_FONT_SIZE: f32 = 30.;
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+pub struct CalibrationPlugin;
+
+/// The metronome and the offsets of the last taps, in seconds
+#[derive(Resource)]
+struct Metronome {
+    timer: Timer,
+    taps: VecDeque<f32>,
+}
+
+impl Metronome {
+    fn average(&self) -> Option<f32> {
+        if self.taps.is_empty() {
+            None
+        } else {
+            Some(self.taps.iter().sum::<f32>() / self.taps.len() as f32)
+        }
+    }
+}
+
+#[derive(Component)]
+struct CalibrationScreen;
+#[derive(Component)]
+struct CalibrationText;
+#[derive(Component)]
+struct SaveOffsetButton;
+
+impl Plugin for CalibrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Calibration), setup_calibration_screen)
+            .add_systems(
+                Update,
+                (tick_metronome, record_tap, click_save_offset_button)
+                    .chain()
+                    .run_if(in_state(GameState::Calibration)),
+            )
+            .add_systems(
+                Update,
+                update_calibration_text.run_if(in_state(GameState::Calibration)),
+            )
+            .add_systems(
+                OnExit(GameState::Calibration),
+                despawn_screen::<CalibrationScreen>,
+            );
+    }
+}
+
+fn setup_calibration_screen(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands.insert_resource(Metronome {
+        timer: Timer::from_seconds(METRONOME_PERIOD, TimerMode::Repeating),
+        taps: VecDeque::new(),
+    });
+    let style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: CALIBRATION_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(CalibrationScreen)
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section("Calibration", style.clone()).with_style(Style {
+                    margin: UiRect::all(Val::Px(20.)),
+                    ..default()
+                }),
+            );
+            parent
+                .spawn(
+                    TextBundle::from_section("", style.clone())
+                        .with_text_alignment(TextAlignment::Center),
+                )
+                .insert(CalibrationText);
+            spawn_menu_button(parent, &style, "Save offset", SaveOffsetButton);
+            spawn_menu_button(parent, &style, "Back", BackButton);
+        });
+}
+
+fn tick_metronome(
+    time: Res<Time>,
+    ui: Res<AudioChannel<UiChannel>>,
+    audio_assets: Res<AudioAssets>,
+    mut metronome: ResMut<Metronome>,
+) {
+    metronome.timer.tick(time.delta());
+    if metronome.timer.just_finished() {
+        ui.play(audio_assets.debug_beep.clone());
+    }
+}
+
+/// The offset of a tap is counted from the closest beep
+fn record_tap(
+    keyboard_input: Res<Input<KeyCode>>,
+    button_input: Res<Input<GamepadButton>>,
+    mut metronome: ResMut<Metronome>,
+) {
+    if keyboard_input.get_just_pressed().next().is_none()
+        && button_input.get_just_pressed().next().is_none()
+    {
+        return;
+    }
+    let elapsed = metronome.timer.elapsed_secs();
+    let offset = if elapsed < METRONOME_PERIOD / 2. {
+        elapsed
+    } else {
+        elapsed - METRONOME_PERIOD
+    };
+    if metronome.taps.len() >= CALIBRATION_TAPS {
+        metronome.taps.pop_front();
+    }
+    metronome.taps.push_back(offset);
+}
+
+#[allow(clippy::type_complexity)]
+fn click_save_offset_button(
+    metronome: Res<Metronome>,
+    mut settings: ResMut<Settings>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<SaveOffsetButton>)>,
+) {
+    if !interaction_query.iter().any(|x| *x == Interaction::Pressed) {
+        return;
+    }
+    let Some(average) = metronome.average() else {
+        return;
+    };
+    if metronome.taps.len() < CALIBRATION_TAPS {
+        return;
+    }
+    settings.input_offset = average;
+    save_settings(&settings);
+    info!("input offset set to {} s", average);
+}
+
+fn update_calibration_text(
+    metronome: Res<Metronome>,
+    settings: Res<Settings>,
+    mut query: Query<&mut Text, With<CalibrationText>>,
+) {
+    let measured = match metronome.average() {
+        Some(average) if metronome.taps.len() >= CALIBRATION_TAPS => {
+            format!("Measured offset: {:+.0} ms", average * 1000.)
+        }
+        _ => format!("Taps: {}/{}", metronome.taps.len(), CALIBRATION_TAPS),
+    };
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "Press any key or button on each beep\n{}\nSaved offset: {:+.0} ms",
+            measured,
+            settings.input_offset * 1000.
+        );
+    }
+}
@@ -1,12 +1,8 @@
-use crate::audio::{SONG_1, SONG_2};
-use crate::loading::{AudioAssets, TextureAssets};
-use crate::logic::CLOUD_EASING;
-use crate::{
-    actions::GameControl,
-    logic::{CloudControl, GridState, PUSH_COOLDOWN_FACTOR},
-    player::TILE_SIZE,
-    GameState,
-};
+pub use crate::board::CloudDir;
+use crate::board::{BoardCloud, BoardEvent, BoardGeometry};
+use crate::loading::TextureAssets;
+use crate::logic::{grid_to_vec, BoardUpdate, GameBoard, CLOUD_EASING};
+use crate::{player::TILE_SIZE, GameState};
 use bevy::prelude::*;
 use bevy_easings::EaseFunction;
 use colored::*;
@@ -19,8 +15,10 @@ pub struct CloudPlugin;
 //     fn grid_move(&self);
 // }
 
+/// The id matches the one of the cloud on the board
 #[derive(Component)]
 pub struct Cloud {
+    pub id: u32,
     pub dir: CloudDir,
 }
 
@@ -31,28 +29,6 @@ pub struct GridPos {
     pub is_pushed: bool,
 }
 
-#[derive(Component)]
-pub struct LeftCloud;
-
-#[derive(Component)]
-pub struct RightCloud;
-#[derive(Component)]
-pub struct UpCloud;
-#[derive(Component)]
-pub struct DownCloud;
-
-#[derive(Component)]
-pub struct ToDespawn;
-
-#[derive(Default, Eq, PartialEq, Debug, Copy, Clone)]
-pub enum CloudDir {
-    #[default]
-    Up,
-    Down,
-    Left,
-    Right,
-}
-
 #[derive(Component)]
 pub struct IsCooldown {
     pub val: bool,
@@ -71,134 +47,112 @@ pub struct Animation {
     pub state: AnimationState,
 }
 
-#[derive(Component, Deref, DerefMut)]
-pub struct CooldownTimer {
-    pub timer: Timer,
+/// Texture of a cloud depending on its direction and whether it is cooling
+/// down after a push
+pub fn cloud_texture(dir: CloudDir, is_cooling: bool) -> &'static str {
+    match (dir, is_cooling) {
+        (CloudDir::Up, false) => "textures/up_cloud.png",
+        (CloudDir::Down, false) => "textures/down_cloud.png",
+        (CloudDir::Left, false) => "textures/left_cloud.png",
+        (CloudDir::Right, false) => "textures/right_cloud.png",
+        (CloudDir::Up, true) => "textures/up_cooldown.png",
+        (CloudDir::Down, true) => "textures/down_cooldown.png",
+        (CloudDir::Left, true) => "textures/left_cooldown.png",
+        (CloudDir::Right, true) => "textures/right_cooldown.png",
+    }
+}
+
+/// Spawn the entity of a cloud of the board, with a sprite bundle matching its
+/// direction
+pub fn spawn_cloud(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    cloud: &BoardCloud,
+    geometry: &BoardGeometry,
+) {
+    commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load(cloud_texture(cloud.dir, cloud.cooling)),
+            transform: Transform::from_translation(grid_to_vec(cloud.pos, geometry)),
+            ..default()
+        },
+        Cloud {
+            id: cloud.id,
+            dir: cloud.dir,
+        },
+        IsCooldown { val: cloud.cooling },
+        GridPos {
+            pos: cloud.pos,
+            old_pos: cloud.pos,
+            is_pushed: false,
+        },
+        Animation {
+            state: AnimationState::Init,
+        },
+    ));
 }
 
-pub fn new_cloud(
-    mut cloud_control: ResMut<CloudControl>,
-    mut grid_state: ResMut<GridState>,
+/// Spawn, move and despawn the cloud entities following what happened on the
+/// board
+#[allow(clippy::type_complexity)]
+pub fn render_board_events(
     mut commands: Commands,
-    audio_assets: Res<AudioAssets>,
+    mut board_events: EventReader<BoardUpdate>,
+    game_board: Res<GameBoard>,
     asset_server: Res<AssetServer>,
+    mut query: Query<(
+        Entity,
+        &mut Cloud,
+        &mut GridPos,
+        &mut IsCooldown,
+        &mut Handle<Image>,
+    )>,
 ) {
-    let beat_length = match audio_assets.selected_song {
-        crate::audio::SelectedSong::Song1 => SONG_1.beat_length,
-        crate::audio::SelectedSong::Song2 => SONG_2.beat_length,
-    };
-    if let Some(cloud_dir) = cloud_control.cur_new_cloud {
-        // Spawn a new cloud, with a sprite bundle, associate the direction
-        if let Some((cloud_pos_vec, cloud_pos_grid)) = grid_state.new_cloud(cloud_dir) {
-            match cloud_dir {
-                CloudDir::Down => {
-                    commands
-                        .spawn(SpriteBundle {
-                            texture: asset_server.load("textures/down_cloud.png"),
-                            transform: Transform::from_translation(cloud_pos_vec),
-                            ..default()
-                        })
-                        .insert(DownCloud)
-                        .insert(CooldownTimer {
-                            timer: Timer::from_seconds(
-                                PUSH_COOLDOWN_FACTOR * beat_length,
-                                TimerMode::Once,
-                            ),
-                        })
-                        .insert(Cloud {
-                            dir: CloudDir::Down,
-                        })
-                        .insert(IsCooldown { val: false })
-                        .insert(GridPos {
-                            pos: cloud_pos_grid,
-                            old_pos: cloud_pos_grid,
-                            is_pushed: false,
-                        })
-                        .insert(Animation {
-                            state: AnimationState::Init,
-                        });
+    for event in board_events.iter() {
+        match **event {
+            BoardEvent::CloudSpawned { id, pos, dir } => {
+                let cloud = BoardCloud {
+                    id,
+                    pos,
+                    dir,
+                    cooling: false,
+                    cooldown: 0,
+                };
+                spawn_cloud(&mut commands, &asset_server, &cloud, game_board.geometry());
+            }
+            BoardEvent::CloudMoved { id, to, pushed, .. } => {
+                for (_, cloud, mut cloud_pos, _, _) in query.iter_mut() {
+                    if cloud.id == id {
+                        cloud_pos.old_pos = cloud_pos.pos;
+                        cloud_pos.pos = to;
+                        cloud_pos.is_pushed = pushed;
+                    }
                 }
-                CloudDir::Left => {
-                    commands
-                        .spawn(SpriteBundle {
-                            texture: asset_server.load("textures/left_cloud.png"),
-                            transform: Transform::from_translation(cloud_pos_vec),
-                            ..default()
-                        })
-                        .insert(LeftCloud)
-                        .insert(CooldownTimer {
-                            timer: Timer::from_seconds(
-                                PUSH_COOLDOWN_FACTOR * beat_length,
-                                TimerMode::Once,
-                            ),
-                        })
-                        .insert(Cloud {
-                            dir: CloudDir::Left,
-                        })
-                        .insert(IsCooldown { val: false })
-                        .insert(GridPos {
-                            pos: cloud_pos_grid,
-                            old_pos: cloud_pos_grid,
-                            is_pushed: false,
-                        })
-                        .insert(Animation {
-                            state: AnimationState::Init,
-                        });
+            }
+            BoardEvent::CloudCooldown { id, cooling } => {
+                for (_, cloud, _, mut is_cooling, mut texture) in query.iter_mut() {
+                    if cloud.id == id {
+                        is_cooling.val = cooling;
+                        *texture = asset_server.load(cloud_texture(cloud.dir, cooling));
+                    }
                 }
-                CloudDir::Up => {
-                    commands
-                        .spawn(SpriteBundle {
-                            texture: asset_server.load("textures/up_cloud.png"),
-                            transform: Transform::from_translation(cloud_pos_vec),
-                            ..default()
-                        })
-                        .insert(UpCloud)
-                        .insert(CooldownTimer {
-                            timer: Timer::from_seconds(
-                                PUSH_COOLDOWN_FACTOR * beat_length,
-                                TimerMode::Once,
-                            ),
-                        })
-                        .insert(IsCooldown { val: false })
-                        .insert(Cloud { dir: CloudDir::Up })
-                        .insert(GridPos {
-                            pos: cloud_pos_grid,
-                            old_pos: cloud_pos_grid,
-                            is_pushed: false,
-                        })
-                        .insert(Animation {
-                            state: AnimationState::Init,
-                        });
+            }
+            BoardEvent::CloudTurned { id, dir } => {
+                for (_, mut cloud, _, is_cooling, mut texture) in query.iter_mut() {
+                    if cloud.id == id {
+                        cloud.dir = dir;
+                        *texture = asset_server.load(cloud_texture(dir, is_cooling.val));
+                    }
                 }
-                CloudDir::Right => {
-                    commands
-                        .spawn(SpriteBundle {
-                            texture: asset_server.load("textures/right_cloud.png"),
-                            transform: Transform::from_translation(cloud_pos_vec),
-                            ..default()
-                        })
-                        .insert(RightCloud)
-                        .insert(Cloud {
-                            dir: CloudDir::Right,
-                        })
-                        .insert(CooldownTimer {
-                            timer: Timer::from_seconds(
-                                PUSH_COOLDOWN_FACTOR * beat_length,
-                                TimerMode::Once,
-                            ),
-                        })
-                        .insert(IsCooldown { val: false })
-                        .insert(GridPos {
-                            pos: cloud_pos_grid,
-                            old_pos: cloud_pos_grid,
-                            is_pushed: false,
-                        })
-                        .insert(Animation {
-                            state: AnimationState::Init,
-                        });
+            }
+            BoardEvent::CloudDespawned { id } | BoardEvent::CloudPushedOff { id, .. } => {
+                for (entity, cloud, _, _, _) in query.iter_mut() {
+                    if cloud.id == id {
+                        commands.entity(entity).despawn();
+                    }
                 }
             }
+            _ => (),
         }
     }
-    cloud_control.cur_new_cloud = None;
 }
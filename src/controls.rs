@@ -0,0 +1,272 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Screen of the controls, reached from the menu. Clicking a control then
+//! pressing a key or a gamepad button binds it to the control instead of its
+//! previous ones. A key or button bound to another control is taken from it,
+//! with a warning. The keys used by the runs themselves, like the one of the
+//! pause, cannot be bound. The bindings are saved at once, see
+//! [`crate::actions`].
+
+use crate::actions::{Bindings, GameControl, BINDINGS_FILE, CONTROLS};
+use crate::loading::FontAssets;
+use crate::menu::{despawn_screen, spawn_menu_button, BackButton};
+use crate::pause::PAUSE_KEY;
+use crate::practice::END_PRACTICE_KEY;
+use crate::rewind::REWIND_KEY;
+use crate::save::{data_dir, save_ron};
+use crate::GameState;
+use bevy::prelude::*;
+
+pub const CONTROLS_FONT_SIZE: f32 = 30.;
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const SELECTED_COLOR: Color = Color::GOLD;
+const WARNING_COLOR: Color = Color::ORANGE_RED;
+// Keys handled outside of the bindings, with what they do:
+const RESERVED_KEYS: [(KeyCode, &str); 3] = [
+    (PAUSE_KEY, "pause"),
+    (REWIND_KEY, "rewind"),
+    (END_PRACTICE_KEY, "end the practice"),
+];
+
+pub struct ControlsPlugin;
+
+/// The control waiting for a key, if any, and the last warning shown
+#[derive(Resource, Default)]
+struct Rebinding {
+    control: Option<GameControl>,
+    warning: String,
+}
+
+#[derive(Component)]
+struct ControlsScreen;
+#[derive(Component)]
+struct ControlButton(GameControl);
+#[derive(Component)]
+struct ControlText(GameControl);
+#[derive(Component)]
+struct WarningText;
+#[derive(Component)]
+struct DefaultBindingsButton;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Rebinding>()
+            .add_systems(OnEnter(GameState::Controls), setup_controls_screen)
+            .add_systems(
+                Update,
+                (
+                    click_control_button,
+                    click_default_bindings_button,
+                    rebind_control,
+                    update_controls_text,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Controls)),
+            )
+            .add_systems(
+                OnExit(GameState::Controls),
+                despawn_screen::<ControlsScreen>,
+            );
+    }
+}
+
+/// Keys then gamepad buttons of a control, like `Up: W, Up, DPadUp`
+fn describe_bindings(bindings: &Bindings, control: GameControl) -> String {
+    let inputs: Vec<String> = bindings
+        .keys_of(control)
+        .map(|x| format!("{:?}", x))
+        .chain(bindings.buttons_of(control).map(|x| format!("{:?}", x)))
+        .collect();
+    if inputs.is_empty() {
+        format!("{:?}: none", control)
+    } else {
+        format!("{:?}: {}", control, inputs.join(", "))
+    }
+}
+
+fn save_bindings(bindings: &Bindings) {
+    let path = data_dir().join(BINDINGS_FILE);
+    if let Err(err) = save_ron(&path, bindings) {
+        error!("could not save the bindings {:?}: {}", path, err);
+    }
+}
+
+fn setup_controls_screen(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    *rebinding = Rebinding::default();
+    let style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: CONTROLS_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+    let button_style = Style {
+        width: Val::Px(600.0),
+        height: Val::Px(50.0),
+        margin: UiRect::bottom(Val::Px(10.)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(ControlsScreen)
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section("Controls", style.clone()).with_style(Style {
+                    margin: UiRect::all(Val::Px(20.)),
+                    ..default()
+                }),
+            );
+            for control in CONTROLS {
+                parent
+                    .spawn(ButtonBundle {
+                        style: button_style.clone(),
+                        ..Default::default()
+                    })
+                    .insert(ControlButton(control))
+                    .with_children(|parent| {
+                        parent
+                            .spawn(TextBundle::from_section("", style.clone()))
+                            .insert(ControlText(control));
+                    });
+            }
+            parent
+                .spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        color: WARNING_COLOR,
+                        ..style.clone()
+                    },
+                ))
+                .insert(WarningText);
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        margin: UiRect::top(Val::Px(20.)),
+                        ..button_style.clone()
+                    },
+                    ..Default::default()
+                })
+                .insert(DefaultBindingsButton)
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Default controls", style.clone()));
+                });
+            spawn_menu_button(parent, &style, "Back", BackButton);
+        });
+}
+
+/// Select the control to rebind, a second click cancels
+fn click_control_button(
+    mut rebinding: ResMut<Rebinding>,
+    interaction_query: Query<(&Interaction, &ControlButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        rebinding.warning.clear();
+        if rebinding.control == Some(button.0) {
+            rebinding.control = None;
+        } else {
+            rebinding.control = Some(button.0);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_default_bindings_button(
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<DefaultBindingsButton>)>,
+) {
+    if interaction_query.iter().any(|x| *x == Interaction::Pressed) {
+        *bindings = Bindings::default();
+        *rebinding = Rebinding::default();
+        save_bindings(&bindings);
+    }
+}
+
+/// Bind the first key or gamepad button pressed to the selected control. A
+/// reserved key is refused, the control waiting for another one.
+fn rebind_control(
+    keyboard_input: Res<Input<KeyCode>>,
+    button_input: Res<Input<GamepadButton>>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    let Some(control) = rebinding.control else {
+        return;
+    };
+    // The other control the input was bound to:
+    let taken_from;
+    let input;
+    if let Some(key) = keyboard_input.get_just_pressed().next() {
+        if let Some((_, action)) = RESERVED_KEYS.iter().find(|x| x.0 == *key) {
+            rebinding.warning = format!("{:?} is already used to {}", key, action);
+            return;
+        }
+        input = format!("{:?}", key);
+        taken_from = bindings
+            .keys
+            .iter()
+            .find(|x| x.0 == *key && x.1 != control)
+            .map(|x| x.1);
+        bindings.keys.retain(|x| x.0 != *key && x.1 != control);
+        bindings.keys.push((*key, control));
+    } else if let Some(button) = button_input.get_just_pressed().next() {
+        let button = button.button_type;
+        input = format!("{:?}", button);
+        taken_from = bindings
+            .buttons
+            .iter()
+            .find(|x| x.0 == button && x.1 != control)
+            .map(|x| x.1);
+        bindings.buttons.retain(|x| x.0 != button && x.1 != control);
+        bindings.buttons.push((button, control));
+    } else {
+        return;
+    }
+    rebinding.control = None;
+    rebinding.warning = match taken_from {
+        Some(other)
+            if bindings.keys_of(other).next().is_none()
+                && bindings.buttons_of(other).next().is_none() =>
+        {
+            format!("{} was bound to {:?}, which has nothing left", input, other)
+        }
+        Some(other) => format!("{} was bound to {:?} too", input, other),
+        None => String::new(),
+    };
+    save_bindings(&bindings);
+}
+
+fn update_controls_text(
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+    mut control_query: Query<(&mut Text, &ControlText), Without<WarningText>>,
+    mut warning_query: Query<&mut Text, With<WarningText>>,
+) {
+    for (mut text, control_text) in control_query.iter_mut() {
+        if rebinding.control == Some(control_text.0) {
+            text.sections[0].value = format!("{:?}: press a key or a button", control_text.0);
+            text.sections[0].style.color = SELECTED_COLOR;
+        } else {
+            text.sections[0].value = describe_bindings(&bindings, control_text.0);
+            text.sections[0].style.color = TEXT_COLOR;
+        }
+    }
+    for mut text in warning_query.iter_mut() {
+        text.sections[0].value = rebinding.warning.clone();
+    }
+}
@@ -0,0 +1,216 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Difficulty of the runs, chosen in the menu. The Custom level reads its
+//! values from `custom_difficulty.ron` in the data directory, which is written
+//! with the Normal values the first time it is chosen. On top of it, the ramp
+//! of `assets/default.ramp.ron` makes the rules harder as a run goes on.
+
+use crate::board::BoardConfig;
+use crate::loading::DataAssets;
+use crate::ramp::Ramp;
+use crate::save::{data_dir, load_ron, save_ron};
+use crate::score::Judgement;
+use crate::GameState;
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use serde::{Deserialize, Serialize};
+
+pub const CUSTOM_DIFFICULTY_FILE: &str = "custom_difficulty.ron";
+// How far from the beat the player can be and still be on it, in seconds:
+pub const FORGIVENESS_MARGIN: f32 = 0.05;
+pub const EASY_FORGIVENESS_MARGIN: f32 = 0.08;
+pub const HARD_FORGIVENESS_MARGIN: f32 = 0.035;
+// How far from the beat an input is still Perfect, in seconds:
+pub const PERFECT_WINDOW: f32 = 0.02;
+pub const EASY_PERFECT_WINDOW: f32 = 0.03;
+pub const HARD_PERFECT_WINDOW: f32 = 0.015;
+// How far after the beat an input missing it is still Late, in seconds:
+pub const LATE_WINDOW: f32 = 0.1;
+pub const EASY_LATE_WINDOW: f32 = 0.14;
+pub const HARD_LATE_WINDOW: f32 = 0.07;
+
+pub struct DifficultyPlugin;
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DifficultyLevel {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Custom,
+}
+
+impl DifficultyLevel {
+    /// The level after this one in the menu
+    pub fn next(self) -> Self {
+        match self {
+            DifficultyLevel::Easy => DifficultyLevel::Normal,
+            DifficultyLevel::Normal => DifficultyLevel::Hard,
+            DifficultyLevel::Hard => DifficultyLevel::Custom,
+            DifficultyLevel::Custom => DifficultyLevel::Easy,
+        }
+    }
+}
+
+/// Rules of the next runs
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Difficulty {
+    pub level: DifficultyLevel,
+    pub board: BoardConfig,
+    pub forgiveness_margin: f32,
+    #[serde(default)]
+    pub judgement_windows: JudgementWindows,
+}
+
+/// How the inputs are graded from their offset to the beat, in seconds. The
+/// ones inside the forgiveness margin are on the beat: Perfect inside
+/// `perfect`, Good otherwise. The ones after it are Late up to `late`, and
+/// the others Miss.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct JudgementWindows {
+    pub perfect: f32,
+    pub late: f32,
+}
+
+impl Default for JudgementWindows {
+    fn default() -> Self {
+        JudgementWindows {
+            perfect: PERFECT_WINDOW,
+            late: LATE_WINDOW,
+        }
+    }
+}
+
+impl JudgementWindows {
+    /// Grade an input made at this offset from the beat, see
+    /// [`MainClock::beat_offset`](crate::logic::MainClock::beat_offset)
+    pub fn judge(&self, offset: f32, forgiveness_margin: f32) -> Judgement {
+        if offset.abs() < self.perfect.min(forgiveness_margin) {
+            Judgement::Perfect
+        } else if offset.abs() < forgiveness_margin {
+            Judgement::Good
+        } else if offset > 0. && offset <= self.late {
+            Judgement::Late
+        } else {
+            Judgement::Miss
+        }
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::preset(DifficultyLevel::Normal)
+    }
+}
+
+impl Difficulty {
+    pub fn preset(level: DifficultyLevel) -> Self {
+        match level {
+            DifficultyLevel::Easy => Difficulty {
+                level,
+                board: BoardConfig::easy(),
+                forgiveness_margin: EASY_FORGIVENESS_MARGIN,
+                judgement_windows: JudgementWindows {
+                    perfect: EASY_PERFECT_WINDOW,
+                    late: EASY_LATE_WINDOW,
+                },
+            },
+            DifficultyLevel::Normal => Difficulty {
+                level,
+                board: BoardConfig::default(),
+                forgiveness_margin: FORGIVENESS_MARGIN,
+                judgement_windows: JudgementWindows::default(),
+            },
+            DifficultyLevel::Hard => Difficulty {
+                level,
+                board: BoardConfig::hard(),
+                forgiveness_margin: HARD_FORGIVENESS_MARGIN,
+                judgement_windows: JudgementWindows {
+                    perfect: HARD_PERFECT_WINDOW,
+                    late: HARD_LATE_WINDOW,
+                },
+            },
+            DifficultyLevel::Custom => load_custom_difficulty(),
+        }
+    }
+}
+
+fn load_custom_difficulty() -> Difficulty {
+    let path = data_dir().join(CUSTOM_DIFFICULTY_FILE);
+    if path.exists() {
+        match load_ron::<Difficulty>(&path) {
+            Ok(difficulty) => {
+                return Difficulty {
+                    level: DifficultyLevel::Custom,
+                    ..difficulty
+                }
+            }
+            Err(err) => error!("could not load the custom difficulty {:?}: {}", path, err),
+        }
+    }
+    let difficulty = Difficulty {
+        level: DifficultyLevel::Custom,
+        ..Difficulty::preset(DifficultyLevel::Normal)
+    };
+    if !path.exists() {
+        match save_ron(&path, &difficulty) {
+            Ok(()) => info!("edit {:?} to tune the custom difficulty", path),
+            Err(err) => error!("could not save the custom difficulty {:?}: {}", path, err),
+        }
+    }
+    difficulty
+}
+
+/// Ramp of the next runs, the rules stay the same during a run without it
+#[derive(Resource, Debug, Clone, Default)]
+pub struct RampCurve {
+    pub ramp: Option<Ramp>,
+}
+
+/// Content of a `.ramp.ron` file
+#[derive(TypeUuid, TypePath, Debug)]
+#[uuid = "52ddf17a-d496-4636-b4d1-9bb0e9e415d6"]
+pub struct RampAsset(pub Ramp);
+
+#[derive(Default)]
+struct RampLoader;
+
+impl AssetLoader for RampLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let ramp: Ramp = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(RampAsset(ramp)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ramp.ron"]
+    }
+}
+
+fn load_ramp_curve(
+    data_assets: Res<DataAssets>,
+    ramps: Res<Assets<RampAsset>>,
+    mut ramp_curve: ResMut<RampCurve>,
+) {
+    ramp_curve.ramp = ramps.get(&data_assets.ramp).map(|x| x.0.clone());
+    if ramp_curve.ramp.is_none() {
+        warn!("could not load the ramp, the rules stay the same during the runs");
+    }
+}
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<RampAsset>()
+            .init_asset_loader::<RampLoader>()
+            .init_resource::<Difficulty>()
+            .init_resource::<RampCurve>()
+            .add_systems(OnExit(GameState::Loading), load_ramp_curve);
+    }
+}
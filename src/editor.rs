@@ -0,0 +1,572 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Editor of the beatmap of the selected song, reached from the main menu.
+//!
+//! The chart is edited as a single timeline, the first beats being the intro:
+//! - Left/Right: previous/next beat
+//! - click on a border, or Shift + W/A/S/D: toggle a spawn on this beat
+//! - W/A/S/D: toggle the move of the clouds going up/left/down/right
+//! - N: insert a beat after the current one, Backspace: remove the current one
+//! - I: end the intro on the current beat (again to remove the intro)
+//! - Space: play the song from the current beat
+//! - Ctrl + S: save the chart next to the song
+
+use crate::audio::{MusicChannel, SfxChannel};
+use crate::beatmap::{Beatmap, ChartBeat};
+use crate::board::CloudDir;
+use crate::clouds::cloud_texture;
+use crate::difficulty::Difficulty;
+use crate::loading::FontAssets;
+use crate::logic::{grid_to_vec, vec_to_grid};
+use crate::menu::BackButton;
+use crate::save::{assets_dir, save_ron};
+use crate::songs::{CurrentSong, SelectedSong, SongDefinition};
+use crate::world::{Stage, TileMapEntity};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::text::BreakLineOn;
+use bevy_kira_audio::prelude::*;
+
+// Beats shown at once on the timeline:
+const TIMELINE_BARS: usize = 4;
+const BEATS_PER_BAR: usize = 4;
+const TIMELINE_FONT_SIZE: f32 = 20.;
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const BEAT_COLOR: Color = Color::rgba(0., 0., 0., 0.6);
+const BAR_COLOR: Color = Color::rgba(0.2, 0.2, 0.2, 0.8);
+const INTRO_COLOR: Color = Color::rgba(0.1, 0.2, 0.4, 0.8);
+const CURSOR_COLOR: Color = Color::rgba(0.6, 0.45, 0., 0.9);
+// Transparency of the clouds showing the spawns of the current beat:
+const SPAWN_PREVIEW_ALPHA: f32 = 0.6;
+
+pub struct EditorPlugin;
+
+/// The chart being edited. The first `intro_length` beats are the intro, the
+/// following ones are looped.
+#[derive(Default, Resource)]
+pub struct BeatmapEditor {
+    pub beats: Vec<ChartBeat>,
+    pub intro_length: usize,
+    pub cursor: usize,
+    /// Whether the chart changed since it was loaded or saved
+    pub dirty: bool,
+}
+
+impl BeatmapEditor {
+    fn from_beatmap(beatmap: &Beatmap) -> Self {
+        let mut beats = beatmap.intro.clone();
+        beats.extend(beatmap.beats.iter().cloned());
+        if beats.is_empty() {
+            beats.push(ChartBeat::default());
+        }
+        BeatmapEditor {
+            beats,
+            intro_length: beatmap.intro.len(),
+            cursor: 0,
+            dirty: false,
+        }
+    }
+
+    pub fn beatmap(&self) -> Beatmap {
+        Beatmap {
+            intro: self.beats[..self.intro_length].to_vec(),
+            beats: self.beats[self.intro_length..].to_vec(),
+        }
+    }
+
+    /// The beat following the cursor when the chart is played, the loop
+    /// starting after the intro
+    fn next_beat(&self) -> usize {
+        if self.cursor + 1 < self.beats.len() {
+            self.cursor + 1
+        } else if self.intro_length < self.beats.len() {
+            self.intro_length
+        } else {
+            0
+        }
+    }
+}
+
+/// Present while the song is played from the editor
+#[derive(Resource)]
+struct Audition {
+    instance: Handle<AudioInstance>,
+    beat_timer: Timer,
+}
+
+#[derive(Component)]
+struct EditorScreen;
+#[derive(Component)]
+struct SpawnPreview;
+#[derive(Component)]
+struct EditorInfoText;
+#[derive(Component)]
+struct TimelineCell {
+    slot: usize,
+    bar_start: bool,
+}
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BeatmapEditor>()
+            .add_systems(OnEnter(GameState::Editor), (load_beatmap, setup_editor))
+            .add_systems(
+                Update,
+                (
+                    edit_beatmap,
+                    click_border,
+                    audition_beatmap,
+                    save_beatmap,
+                    update_spawn_preview,
+                    update_timeline,
+                    update_info_text,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Editor)),
+            )
+            .add_systems(OnExit(GameState::Editor), (stop_audition, despawn_editor));
+    }
+}
+
+/// Start from the chart of the song, or from the rules of the selected
+/// difficulty if it has none
+fn load_beatmap(
+    current_song: CurrentSong,
+    difficulty: Res<Difficulty>,
+    mut editor: ResMut<BeatmapEditor>,
+) {
+    *editor = match &current_song.get().beatmap {
+        Some(beatmap) => BeatmapEditor::from_beatmap(beatmap),
+        None => BeatmapEditor::from_beatmap(&Beatmap::from_config(&difficulty.board)),
+    };
+}
+
+fn setup_editor(mut commands: Commands, font_assets: Res<FontAssets>) {
+    let style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: TIMELINE_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+
+    // Help and status, on the top left:
+    commands.spawn((
+        TextBundle::from_section("", style.clone()).with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.),
+            top: Val::Px(10.),
+            ..default()
+        }),
+        EditorScreen,
+        EditorInfoText,
+    ));
+
+    // Timeline, under the tilemap:
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                bottom: Val::Percent(3.),
+                flex_direction: FlexDirection::Row,
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(EditorScreen)
+        .with_children(|parent| {
+            for bar in 0..TIMELINE_BARS {
+                for beat in 0..BEATS_PER_BAR {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                width: Val::Px(70.),
+                                height: Val::Px(80.),
+                                margin: UiRect::all(Val::Px(2.)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .insert(TimelineCell {
+                            slot: bar * BEATS_PER_BAR + beat,
+                            bar_start: beat == 0,
+                        })
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle {
+                                text: Text {
+                                    sections: vec![TextSection::new("", style.clone())],
+                                    alignment: TextAlignment::Center,
+                                    linebreak_behavior: BreakLineOn::WordBoundary,
+                                },
+                                ..default()
+                            });
+                        });
+                }
+            }
+        });
+
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(120.0),
+                height: Val::Px(50.0),
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.),
+                top: Val::Px(10.),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(EditorScreen)
+        .insert(BackButton)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Back",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 40.0,
+                    color: TEXT_COLOR,
+                },
+            ));
+        });
+}
+
+fn toggle_dir(dirs: &mut Vec<CloudDir>, dir: CloudDir) {
+    match dirs.iter().position(|x| *x == dir) {
+        Some(ndx) => {
+            dirs.remove(ndx);
+        }
+        None => dirs.push(dir),
+    }
+}
+
+fn edit_beatmap(keyboard_input: Res<Input<KeyCode>>, mut editor: ResMut<BeatmapEditor>) {
+    let editor = &mut *editor;
+    if keyboard_input.just_pressed(KeyCode::Right) && editor.cursor + 1 < editor.beats.len() {
+        editor.cursor += 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::Left) {
+        editor.cursor = editor.cursor.saturating_sub(1);
+    }
+    // Ctrl + S saves the chart:
+    if keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+
+    let shift = keyboard_input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    for (key, dir) in [
+        (KeyCode::W, CloudDir::Up),
+        (KeyCode::A, CloudDir::Left),
+        (KeyCode::S, CloudDir::Down),
+        (KeyCode::D, CloudDir::Right),
+    ] {
+        if keyboard_input.just_pressed(key) {
+            let beat = &mut editor.beats[editor.cursor];
+            toggle_dir(
+                if shift {
+                    &mut beat.spawns
+                } else {
+                    &mut beat.moves
+                },
+                dir,
+            );
+            editor.dirty = true;
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::N) {
+        if editor.cursor < editor.intro_length {
+            editor.intro_length += 1;
+        }
+        editor.cursor += 1;
+        editor.beats.insert(editor.cursor, ChartBeat::default());
+        editor.dirty = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) && editor.beats.len() > 1 {
+        editor.beats.remove(editor.cursor);
+        if editor.cursor < editor.intro_length {
+            editor.intro_length -= 1;
+        }
+        editor.cursor = editor.cursor.min(editor.beats.len() - 1);
+        editor.dirty = true;
+    }
+    if keyboard_input.just_pressed(KeyCode::I) {
+        editor.intro_length = if editor.intro_length == editor.cursor + 1 {
+            0
+        } else {
+            editor.cursor + 1
+        };
+        editor.dirty = true;
+    }
+}
+
+/// Clicking a tile where clouds spawn toggles the spawn of its border
+fn click_border(
+    mouse_input: Res<Input<MouseButton>>,
+    window_query: Query<&Window>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut editor: ResMut<BeatmapEditor>,
+    stage: Res<Stage>,
+) {
+    if !mouse_input.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(cursor_pos) = window_query.single().cursor_position() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let Some(pos) = camera.viewport_to_world_2d(camera_transform, cursor_pos) else {
+        return;
+    };
+    let tile = vec_to_grid(pos, &stage.geometry);
+    let border = [
+        CloudDir::Up,
+        CloudDir::Down,
+        CloudDir::Left,
+        CloudDir::Right,
+    ]
+    .into_iter()
+    .find(|dir| stage.geometry.spawn_line(*dir).contains(&tile));
+    if let Some(border) = border {
+        let cursor = editor.cursor;
+        toggle_dir(&mut editor.beats[cursor].spawns, border);
+        editor.dirty = true;
+    }
+}
+
+/// Space plays the song from the current beat, the cursor following the
+/// music. The move sample marks the beats with spawns.
+#[allow(clippy::too_many_arguments)]
+fn audition_beatmap(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    current_song: CurrentSong,
+    music: Res<AudioChannel<MusicChannel>>,
+    sfx: Res<AudioChannel<SfxChannel>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    audition: Option<ResMut<Audition>>,
+    mut editor: ResMut<BeatmapEditor>,
+) {
+    let song = current_song.get();
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        match &audition {
+            Some(audition) => {
+                if let Some(instance) = audio_instances.get_mut(&audition.instance) {
+                    instance.stop(AudioTween::default());
+                }
+                commands.remove_resource::<Audition>();
+            }
+            None => {
+                // The first beat of a run is played one beat after the start
+                // of the song:
+                let mut position = (editor.cursor + 1) as f32 * song.beat_length;
+                let loop_end = song.intro_length + song.loop_length;
+                if position >= loop_end {
+                    position =
+                        song.intro_length + (position - song.intro_length) % song.loop_length;
+                }
+                let instance = music
+                    .play(song.track.clone())
+                    .start_from(position as f64)
+                    .loop_from(song.intro_length as f64)
+                    .loop_until(loop_end as f64)
+                    .with_volume(song.volume)
+                    .handle();
+                commands.insert_resource(Audition {
+                    instance,
+                    beat_timer: Timer::from_seconds(song.beat_length, TimerMode::Repeating),
+                });
+            }
+        }
+        return;
+    }
+
+    if let Some(mut audition) = audition {
+        audition.beat_timer.tick(time.delta());
+        if audition.beat_timer.just_finished() {
+            editor.cursor = editor.next_beat();
+            if !editor.beats[editor.cursor].spawns.is_empty() {
+                sfx.play(song.move_sample.clone());
+            }
+        }
+    }
+}
+
+/// Ctrl + S writes the chart in the assets, where the song file points to
+fn save_beatmap(
+    keyboard_input: Res<Input<KeyCode>>,
+    selected_song: Res<SelectedSong>,
+    mut songs: ResMut<Assets<SongDefinition>>,
+    mut editor: ResMut<BeatmapEditor>,
+) {
+    let ctrl = keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if !(ctrl && keyboard_input.just_pressed(KeyCode::S)) {
+        return;
+    }
+    let Some(song) = songs.get_mut(&selected_song.handle) else {
+        return;
+    };
+    let asset_path = song
+        .beatmap_path
+        .clone()
+        .unwrap_or_else(|| format!("beatmaps/{}.beatmap.ron", song.id));
+    let path = assets_dir().join(&asset_path);
+    let beatmap = editor.beatmap();
+    match save_ron(&path, &beatmap) {
+        Ok(()) => {
+            info!("beatmap saved to {:?}", path);
+            if song.beatmap_path.is_none() {
+                warn!(
+                    "add `beatmap: \"{}\"` to the file of {} to load it next time",
+                    asset_path, song.id
+                );
+            }
+            // The next runs use the chart right away:
+            song.beatmap = Some(beatmap);
+            song.beatmap_path = Some(asset_path);
+            editor.dirty = false;
+        }
+        Err(err) => error!("could not save the beatmap {:?}: {}", path, err),
+    }
+}
+
+/// Show the clouds spawned on the current beat on their border
+fn update_spawn_preview(
+    mut commands: Commands,
+    editor: Res<BeatmapEditor>,
+    asset_server: Res<AssetServer>,
+    stage: Res<Stage>,
+    query: Query<Entity, With<SpawnPreview>>,
+) {
+    if !editor.is_changed() {
+        return;
+    }
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for border in editor.beats[editor.cursor].spawns.iter() {
+        for pos in stage.geometry.spawn_line(*border) {
+            commands.spawn((
+                SpriteBundle {
+                    texture: asset_server.load(cloud_texture(*border, false)),
+                    sprite: Sprite {
+                        color: Color::rgba(1., 1., 1., SPAWN_PREVIEW_ALPHA),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(grid_to_vec(pos, &stage.geometry)),
+                    ..default()
+                },
+                EditorScreen,
+                SpawnPreview,
+            ));
+        }
+    }
+}
+
+/// Initials of the directions, in a fixed order
+fn dir_letters(dirs: &[CloudDir]) -> String {
+    [
+        (CloudDir::Up, 'U'),
+        (CloudDir::Down, 'D'),
+        (CloudDir::Left, 'L'),
+        (CloudDir::Right, 'R'),
+    ]
+    .into_iter()
+    .filter(|(dir, _)| dirs.contains(dir))
+    .map(|(_, letter)| letter)
+    .collect()
+}
+
+/// The timeline shows the page of bars containing the cursor
+fn update_timeline(
+    editor: Res<BeatmapEditor>,
+    mut cell_query: Query<(&TimelineCell, &mut BackgroundColor, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !editor.is_changed() {
+        return;
+    }
+    let page_length = TIMELINE_BARS * BEATS_PER_BAR;
+    let page_start = editor.cursor / page_length * page_length;
+    for (cell, mut color, children) in cell_query.iter_mut() {
+        let ndx = page_start + cell.slot;
+        let (label, cell_color) = match editor.beats.get(ndx) {
+            Some(beat) => (
+                format!(
+                    "{}\nS {}\nM {}",
+                    ndx + 1,
+                    dir_letters(&beat.spawns),
+                    dir_letters(&beat.moves)
+                ),
+                if ndx == editor.cursor {
+                    CURSOR_COLOR
+                } else if ndx < editor.intro_length {
+                    INTRO_COLOR
+                } else if cell.bar_start {
+                    BAR_COLOR
+                } else {
+                    BEAT_COLOR
+                },
+            ),
+            None => (String::new(), Color::NONE),
+        };
+        *color = BackgroundColor(cell_color);
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = label.clone();
+            }
+        }
+    }
+}
+
+fn update_info_text(
+    editor: Res<BeatmapEditor>,
+    current_song: CurrentSong,
+    mut query: Query<&mut Text, With<EditorInfoText>>,
+) {
+    if !editor.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "{} - beat {}/{}, intro of {} beats{}\n\
+             Left/Right: beat  W/A/S/D: moves  Shift + W/A/S/D or click: spawns\n\
+             N: new beat  Backspace: remove  I: end of the intro  Space: play  Ctrl + S: save",
+            current_song.get().name,
+            editor.cursor + 1,
+            editor.beats.len(),
+            editor.intro_length,
+            if editor.dirty { " (modified)" } else { "" }
+        );
+    }
+}
+
+fn stop_audition(
+    mut commands: Commands,
+    audition: Option<Res<Audition>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    if let Some(audition) = audition {
+        if let Some(instance) = audio_instances.get_mut(&audition.instance) {
+            instance.stop(AudioTween::default());
+        }
+        commands.remove_resource::<Audition>();
+    }
+}
+
+/// The tilemap is only used by the editor until the next run
+fn despawn_editor(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<EditorScreen>, With<TileMapEntity>)>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
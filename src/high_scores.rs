@@ -0,0 +1,329 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::board::LossCondition;
+use crate::difficulty::{Difficulty, DifficultyLevel};
+use crate::loading::FontAssets;
+use crate::logic::{GameBoard, MainClock};
+use crate::replay::ReplayPlayback;
+use crate::save::{data_dir, load_ron, save_ron};
+use crate::score::Score;
+use crate::songs::CurrentSong;
+use crate::{GameMode, GameState};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const HIGH_SCORES_FILE: &str = "high_scores.ron";
+// Number of entries kept for each song and difficulty:
+pub const HIGH_SCORES_SIZE: usize = 10;
+const TABLE_FONT_SIZE: f32 = 24.;
+const TABLE_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const RECORD_COLOR: Color = Color::GOLD;
+
+pub struct HighScoresPlugin;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub score: u32,
+    /// Survival time in seconds
+    pub duration: f32,
+    pub seed: u32,
+    pub loss: LossCondition,
+    /// Seconds since the Unix epoch, 0 when the clock is not available
+    pub date: u64,
+}
+
+/// Best runs played on a song at a difficulty, sorted from the best score
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScoreTable {
+    /// Id of the song, see [`SongDefinition`](crate::songs::SongDefinition)
+    pub song: String,
+    /// The tables saved before the difficulties were all Normal
+    #[serde(default)]
+    pub difficulty: DifficultyLevel,
+    pub entries: Vec<HighScoreEntry>,
+}
+
+#[derive(Default, Debug, Resource, Serialize, Deserialize)]
+pub struct HighScores {
+    pub tables: Vec<HighScoreTable>,
+}
+
+impl HighScores {
+    pub fn table(&self, song: &str, difficulty: DifficultyLevel) -> Option<&HighScoreTable> {
+        self.tables
+            .iter()
+            .find(|x| x.song == song && x.difficulty == difficulty)
+    }
+
+    /// Add a run to the table of its song and difficulty. Returns its rank if
+    /// it made it to the table.
+    pub fn insert(
+        &mut self,
+        song: &str,
+        difficulty: DifficultyLevel,
+        entry: HighScoreEntry,
+    ) -> Option<usize> {
+        let ndx = match self
+            .tables
+            .iter()
+            .position(|x| x.song == song && x.difficulty == difficulty)
+        {
+            Some(ndx) => ndx,
+            None => {
+                self.tables.push(HighScoreTable {
+                    song: song.to_string(),
+                    difficulty,
+                    entries: Vec::new(),
+                });
+                self.tables.len() - 1
+            }
+        };
+        let entries = &mut self.tables[ndx].entries;
+        // Equal scores keep the oldest run first:
+        let rank = entries
+            .iter()
+            .position(|x| x.score < entry.score)
+            .unwrap_or(entries.len());
+        if rank >= HIGH_SCORES_SIZE {
+            return None;
+        }
+        entries.insert(rank, entry);
+        entries.truncate(HIGH_SCORES_SIZE);
+        Some(rank)
+    }
+}
+
+/// Rank of the last run in its table, to highlight it
+#[derive(Default, Resource)]
+pub struct LastRank {
+    pub rank: Option<usize>,
+}
+
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut App) {
+        let path = data_dir().join(HIGH_SCORES_FILE);
+        let high_scores = if path.exists() {
+            load_ron(&path).unwrap_or_else(|err| {
+                error!("could not load the high scores {:?}: {}", path, err);
+                HighScores::default()
+            })
+        } else {
+            HighScores::default()
+        };
+        app.insert_resource(high_scores)
+            .init_resource::<LastRank>()
+            .add_systems(OnEnter(GameState::GameOver), record_high_score);
+    }
+}
+
+/// Save the run which just ended, replays and puzzles are not recorded
+#[allow(clippy::too_many_arguments)]
+pub fn record_high_score(
+    score: Res<Score>,
+    game_board: Res<GameBoard>,
+    main_clock: Res<MainClock>,
+    current_song: CurrentSong,
+    difficulty: Res<Difficulty>,
+    playback: Option<Res<ReplayPlayback>>,
+    game_mode: Res<GameMode>,
+    mut high_scores: ResMut<HighScores>,
+    mut last_rank: ResMut<LastRank>,
+) {
+    last_rank.rank = None;
+    if playback.is_some() || *game_mode != GameMode::Endless {
+        return;
+    }
+    let entry = HighScoreEntry {
+        score: score.points,
+        duration: game_board.tick as f32 * main_clock.main_timer.duration().as_secs_f32(),
+        seed: game_board.rng.seed(),
+        loss: game_board.loss_condition,
+        date: now(),
+    };
+    last_rank.rank = high_scores.insert(&current_song.get().id, difficulty.level, entry);
+    if last_rank.rank.is_some() {
+        let path = data_dir().join(HIGH_SCORES_FILE);
+        if let Err(err) = save_ron(&path, &*high_scores) {
+            error!("could not save the high scores {:?}: {}", path, err);
+        }
+    }
+}
+
+/// Seconds since the Unix epoch
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
+
+/// `SystemTime::now` panics on the web, the runs are not dated there
+#[cfg(target_arch = "wasm32")]
+fn now() -> u64 {
+    0
+}
+
+/// Spawn one line of text per entry of the table, the highlighted one in gold
+pub fn spawn_score_table(
+    parent: &mut ChildBuilder,
+    font: Handle<Font>,
+    title: String,
+    table: Option<&HighScoreTable>,
+    highlight: Option<usize>,
+) {
+    let style = TextStyle {
+        font,
+        font_size: TABLE_FONT_SIZE,
+        color: TABLE_COLOR,
+    };
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                margin: UiRect::all(Val::Px(20.)),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.6)),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(title, style.clone()));
+            let entries = table.map(|x| x.entries.as_slice()).unwrap_or_default();
+            if entries.is_empty() {
+                parent.spawn(TextBundle::from_section("No run yet", style.clone()));
+            }
+            for (ndx, entry) in entries.iter().enumerate() {
+                let mut line_style = style.clone();
+                if highlight == Some(ndx) {
+                    line_style.color = RECORD_COLOR;
+                }
+                parent.spawn(TextBundle::from_section(
+                    format!(
+                        "{:>2}. {:>6}  {:>5.0}s  {:?}  seed {}  {}",
+                        ndx + 1,
+                        entry.score,
+                        entry.duration,
+                        entry.loss,
+                        entry.seed,
+                        if entry.date > 0 {
+                            format_date(entry.date)
+                        } else {
+                            String::new()
+                        }
+                    ),
+                    line_style,
+                ));
+            }
+        });
+}
+
+/// Date as YYYY-MM-DD, from the number of seconds since the Unix epoch
+fn format_date(secs: u64) -> String {
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (secs / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SONG: &str = "song_1";
+
+    fn entry(score: u32, seed: u32) -> HighScoreEntry {
+        HighScoreEntry {
+            score,
+            duration: 0.,
+            seed,
+            loss: LossCondition::TooMessy,
+            date: 0,
+        }
+    }
+
+    fn scores(high_scores: &HighScores) -> Vec<u32> {
+        high_scores
+            .table(SONG, DifficultyLevel::Normal)
+            .unwrap()
+            .entries
+            .iter()
+            .map(|x| x.score)
+            .collect()
+    }
+
+    #[test]
+    fn insert_returns_the_rank() {
+        let mut high_scores = HighScores::default();
+        let normal = DifficultyLevel::Normal;
+        assert_eq!(high_scores.insert(SONG, normal, entry(10, 0)), Some(0));
+        assert_eq!(high_scores.insert(SONG, normal, entry(30, 0)), Some(0));
+        assert_eq!(high_scores.insert(SONG, normal, entry(20, 0)), Some(1));
+        assert_eq!(scores(&high_scores), [30, 20, 10]);
+        // Each difficulty has its own table:
+        let hard = DifficultyLevel::Hard;
+        assert_eq!(high_scores.insert(SONG, hard, entry(5, 0)), Some(0));
+        assert_eq!(high_scores.tables.len(), 2);
+    }
+
+    #[test]
+    fn insert_keeps_the_best_runs_only() {
+        let mut high_scores = HighScores::default();
+        let normal = DifficultyLevel::Normal;
+        for score in 1..=HIGH_SCORES_SIZE as u32 {
+            high_scores.insert(SONG, normal, entry(score * 10, 0));
+        }
+        // Below the last entry of a full table:
+        assert_eq!(high_scores.insert(SONG, normal, entry(5, 0)), None);
+        assert_eq!(scores(&high_scores).len(), HIGH_SCORES_SIZE);
+        // The last entry leaves the table:
+        assert_eq!(high_scores.insert(SONG, normal, entry(55, 0)), Some(5));
+        let table = scores(&high_scores);
+        assert_eq!(table.len(), HIGH_SCORES_SIZE);
+        assert_eq!(table[5], 55);
+        assert_eq!(table.last(), Some(&20));
+    }
+
+    #[test]
+    fn ties_keep_the_oldest_run_first() {
+        let mut high_scores = HighScores::default();
+        let normal = DifficultyLevel::Normal;
+        assert_eq!(high_scores.insert(SONG, normal, entry(50, 1)), Some(0));
+        assert_eq!(high_scores.insert(SONG, normal, entry(50, 2)), Some(1));
+        let seeds: Vec<_> = high_scores.tables[0]
+            .entries
+            .iter()
+            .map(|x| x.seed)
+            .collect();
+        assert_eq!(seeds, [1, 2]);
+        // A tie with the last entry of a full table does not make it:
+        for _ in 2..HIGH_SCORES_SIZE {
+            high_scores.insert(SONG, normal, entry(50, 3));
+        }
+        assert_eq!(high_scores.insert(SONG, normal, entry(50, 4)), None);
+    }
+
+    #[test]
+    fn format_known_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        // Leap days, the last second of one and the day after:
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_709_164_800), "2024-02-29");
+        assert_eq!(format_date(1_709_251_199), "2024-02-29");
+        assert_eq!(format_date(1_709_251_200), "2024-03-01");
+        // 2100 is not a leap year:
+        assert_eq!(format_date(4_107_542_400 - 86_400), "2100-02-28");
+    }
+}
@@ -1,37 +1,74 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 
+// The rules, which build without Bevy for the simulation:
+pub mod beatmap;
+pub mod board;
+pub mod puzzle;
+pub mod ramp;
+
+#[cfg(feature = "game")]
 mod actions;
+#[cfg(feature = "game")]
 mod audio;
+#[cfg(feature = "game")]
+mod calibration;
+#[cfg(feature = "game")]
 mod clouds;
+#[cfg(feature = "game")]
+mod controls;
+#[cfg(feature = "game")]
+mod difficulty;
+#[cfg(feature = "game")]
+mod editor;
+#[cfg(feature = "game")]
+mod high_scores;
+#[cfg(feature = "game")]
 mod loading;
+#[cfg(feature = "game")]
 mod logic;
+#[cfg(feature = "game")]
 mod menu;
+#[cfg(feature = "game")]
+mod pause;
+#[cfg(feature = "game")]
 mod player;
+#[cfg(feature = "game")]
+mod practice;
+#[cfg(feature = "game")]
+mod puzzle_mode;
+#[cfg(feature = "game")]
+mod replay;
+#[cfg(feature = "game")]
+mod rewind;
+#[cfg(feature = "game")]
+mod save;
+#[cfg(feature = "game")]
+mod score;
+#[cfg(feature = "game")]
+pub mod settings;
+#[cfg(feature = "game")]
+mod songs;
+#[cfg(feature = "game")]
 mod splashscreen;
+#[cfg(feature = "game")]
 mod ui;
+#[cfg(feature = "game")]
 mod world;
 
-use crate::actions::ActionsPlugin;
-use crate::audio::InternalAudioPlugin;
-use crate::clouds::CloudPlugin;
-use crate::loading::LoadingPlugin;
-use crate::logic::LogicPlugin;
-use crate::menu::MenuPlugin;
-use crate::player::PlayerPlugin;
-use crate::ui::UiPlugin;
-use crate::world::WorldPlugin;
-
+#[cfg(feature = "game")]
 use bevy::app::App;
-#[cfg(debug_assertions)]
+#[cfg(all(feature = "game", debug_assertions))]
 use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+#[cfg(feature = "game")]
 use bevy::prelude::*;
+#[cfg(feature = "game")]
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use splashscreen::SplashscreenPlugin;
 // use bevy_prototype_debug_lines::DebugLinesPlugin;
 
 // This example game uses States to separate logic
 // See https://bevy-cheatbook.github.io/programming/states.html
 // Or https://github.com/bevyengine/bevy/blob/main/examples/ecs/state.rs
+#[cfg(feature = "game")]
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 enum GameState {
     // During the loading State the LoadingPlugin will load our assets
@@ -43,32 +80,66 @@ enum GameState {
     // Here the menu is drawn and waiting for player interaction
     Menu,
     GameOver,
+    // Table of the best runs, reached from the menu
+    Scores,
+    // Beatmap editor of the selected song, reached from the menu
+    Editor,
+    // List of the puzzles, reached from the menu
+    LevelSelect,
+    // Bindings of the controls, reached from the menu
+    Controls,
+    // Measure of the input latency, reached from the menu
+    Calibration,
+    // Volumes, window and input offset, reached from the menu
+    Settings,
     // A buffer state to give time to clean all the tiles
     PreRetry,
 }
 
+/// Kind of the next runs, chosen from the menu
+#[cfg(feature = "game")]
+#[derive(Resource, Debug, Clone, Copy, Default, Eq, PartialEq)]
+enum GameMode {
+    /// Clouds keep spawning until the player loses
+    #[default]
+    Endless,
+    /// A fixed board to clear, by its index in `Puzzles`
+    Puzzle(usize),
+    /// Endless rules without any loss, at a chosen tempo
+    Practice,
+}
+
+#[cfg(feature = "game")]
 pub struct GamePlugin;
 
+#[cfg(feature = "game")]
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_state::<GameState>()
+            .init_resource::<GameMode>()
             // .add_plugin(DebugLinesPlugin::default())
-            .add_plugins(SplashscreenPlugin)
-            .add_plugins(LoadingPlugin)
-            .add_plugins(MenuPlugin)
-            .add_plugins(WorldPlugin)
-            .add_plugins(ActionsPlugin)
-            .add_plugins(InternalAudioPlugin)
-            .add_plugins(PlayerPlugin)
-            .add_plugins(UiPlugin)
+            .add_plugins(splashscreen::SplashscreenPlugin)
+            .add_plugins(songs::SongsPlugin)
+            .add_plugins(difficulty::DifficultyPlugin)
+            .add_plugins(loading::LoadingPlugin)
+            .add_plugins(menu::MenuPlugin)
+            .add_plugins(world::WorldPlugin)
+            .add_plugins(actions::ActionsPlugin)
+            .add_plugins(audio::InternalAudioPlugin)
+            .add_plugins(player::PlayerPlugin)
+            .add_plugins(ui::UiPlugin)
+            .add_plugins(score::ScorePlugin)
+            .add_plugins(high_scores::HighScoresPlugin)
             // .add_plugins(WorldInspectorPlugin::new())
-            .add_plugins(LogicPlugin);
-        #[cfg(debug_assertions)]
-        {
-            app.add_systems(Update, bevy::window::close_on_esc)
-            // /.add_plugin(FrameTimeDiagnosticsPlugin::default())
-                // .add_plugin(LogDiagnosticsPlugin::default())
-                ;
-        }
+            .add_plugins(logic::LogicPlugin)
+            .add_plugins(replay::ReplayPlugin)
+            .add_plugins(editor::EditorPlugin)
+            .add_plugins(puzzle_mode::PuzzleModePlugin)
+            .add_plugins(rewind::RewindPlugin)
+            .add_plugins(practice::PracticePlugin)
+            .add_plugins(controls::ControlsPlugin)
+            .add_plugins(calibration::CalibrationPlugin)
+            .add_plugins(pause::PausePlugin)
+            .add_plugins(settings::SettingsPlugin);
     }
 }
@@ -1,6 +1,9 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 
-use crate::{audio::SelectedSong, GameState};
+use crate::difficulty::RampAsset;
+use crate::puzzle_mode::PuzzleAsset;
+use crate::world::StageAsset;
+use crate::{songs::SongDefinition, GameState};
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
 use bevy_kira_audio::AudioSource;
@@ -17,7 +20,8 @@ impl Plugin for LoadingPlugin {
         )
         .add_collection_to_loading_state::<_, FontAssets>(GameState::Loading)
         .add_collection_to_loading_state::<_, AudioAssets>(GameState::Loading)
-        .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading);
+        .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading)
+        .add_collection_to_loading_state::<_, DataAssets>(GameState::Loading);
     }
 }
 
@@ -32,41 +36,11 @@ pub struct FontAssets {
 
 #[derive(AssetCollection, Resource)]
 pub struct AudioAssets {
-    pub selected_song: SelectedSong,
-    #[asset(path = "audio/song_1/song_full.wav.ron")]
-    pub song_1: Handle<AudioSource>,
-    // #[asset(path = "audio/song_1/sample_1.wav.ron")]
-    // pub song_1_sample_1: Handle<AudioSource>,
-    // #[asset(path = "audio/song_1/sample_2.wav.ron")]
-    // pub song_1_sample_2: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/song_full.wav.ron")]
-    pub song_2: Handle<AudioSource>,
+    /// Every song definition found in the folder, see [`SongDefinition`]
+    #[asset(path = "songs", collection(typed))]
+    pub songs: Vec<Handle<SongDefinition>>,
     #[asset(path = "audio/debug_beep_200ms.wav.ron")]
     pub debug_beep: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_1_a.wav")]
-    pub sample_1_a: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_1_b.wav")]
-    pub sample_1_b: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_1_c.wav")]
-    pub sample_1_c: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_1_d.wav")]
-    pub sample_1_d: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_2_a.wav")]
-    pub sample_2_a: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_2_b.wav")]
-    pub sample_2_b: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_2_c.wav")]
-    pub sample_2_c: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_2_d.wav")]
-    pub sample_2_d: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_3_a.wav")]
-    pub sample_3_a: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_3_b.wav")]
-    pub sample_3_b: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_3_c.wav")]
-    pub sample_3_c: Handle<AudioSource>,
-    #[asset(path = "audio/song_2/sample_3_d.wav")]
-    pub sample_3_d: Handle<AudioSource>,
 }
 
 #[derive(AssetCollection, Resource)]
@@ -76,3 +50,18 @@ pub struct TextureAssets {
     #[asset(path = "textures/background_1.jpg")]
     pub background: Handle<Image>,
 }
+
+/// Game data read by the rules, loaded as assets so that they are also found
+/// on the web
+#[derive(AssetCollection, Resource)]
+pub struct DataAssets {
+    /// See [`crate::ramp::Ramp`]
+    #[asset(path = "default.ramp.ron")]
+    pub ramp: Handle<RampAsset>,
+    /// See [`crate::world::Stage`]
+    #[asset(path = "default.stage.ron")]
+    pub stage: Handle<StageAsset>,
+    /// Every puzzle found in the folder, see [`crate::puzzle::Puzzle`]
+    #[asset(path = "puzzles", collection(typed))]
+    pub puzzles: Vec<Handle<PuzzleAsset>>,
+}
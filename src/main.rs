@@ -13,6 +13,7 @@ use bevy::{
     window::{Window, WindowPlugin},
 };
 use bevy_easings::EasingsPlugin;
+use cloud_sweeper::settings::load_settings;
 use cloud_sweeper::GamePlugin;
 use std::io::Cursor;
 use winit::window::{Icon, WindowId};
@@ -23,26 +24,18 @@ pub const CAMERA_LAYER: f32 = 500.;
 // Add moving background
 
 fn main() {
+    // Read before the window is built, which opens with them:
+    let settings = load_settings();
     App::new()
         .add_plugins(
             DefaultPlugins
                 .set(ImagePlugin::default_nearest())
                 .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "Cloud Sweeper".into(),
-                        mode: bevy::window::WindowMode::BorderlessFullscreen,
-                        // resizable: false,
-                        // resolution: WindowResolution::new(
-                        //     11. * TILE_SIZE / DISPLAY_RATIO,
-                        //     10. * TILE_SIZE / DISPLAY_RATIO,
-                        // ),
-                        resolution: WindowResolution::default().with_scale_factor_override(1.0),
-                        present_mode: PresentMode::AutoVsync,
-                        ..default()
-                    }),
+                    primary_window: Some(settings.window()),
                     ..default()
                 }),
         )
+        .insert_resource(settings)
         .insert_resource(Msaa::Off)
         .add_plugins(EasingsPlugin)
         .insert_resource(ClearColor(Color::rgb(0., 0., 0.)))
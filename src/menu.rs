@@ -1,12 +1,19 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
 
-use crate::clouds::{self, Animation, AnimationState, ToDespawn};
-use crate::loading::TextureAssets;
-use crate::logic::{GridState, LossCause};
+use crate::audio::MusicChannel;
+use crate::clouds::{self, Animation, AnimationState};
+use crate::difficulty::Difficulty;
+use crate::high_scores::{record_high_score, spawn_score_table, HighScores, LastRank};
+use crate::loading::{AudioAssets, TextureAssets};
+use crate::logic::{GameBoard, GameSeed, LossCause};
+use crate::pause::{PauseState, LEAVE_RUN_TRANSITIONS};
 use crate::player::{Player, TILE_SIZE};
+use crate::practice::PracticeSettings;
+use crate::score::{JudgementLabel, LevelBanner, Score, ScoreText};
+use crate::songs::{find_song, sorted_songs, CurrentSong, SelectedSong, SongDefinition};
 use crate::ui::{MessBar, MessTile};
-use crate::GameState;
 use crate::{clouds::Cloud, loading::FontAssets};
+use crate::{GameMode, GameState};
 use bevy::app::AppExit;
 use bevy::prelude::*;
 use bevy::text::BreakLineOn;
@@ -15,9 +22,9 @@ use bevy_easings::{Ease, EasingType};
 use bevy_ecs_tilemap::prelude::TilemapTextureSize;
 use bevy_ecs_tilemap::tiles::{TileBundle, TilePos, TileStorage, TileVisible};
 use bevy_kira_audio::prelude::*;
-use bevy_kira_audio::{Audio, AudioEasing, AudioTween};
+use bevy_kira_audio::{AudioEasing, AudioTween};
 // use {AlignItems, BackgroundColor, JustifyContent, UiRect};
-use crate::world::{AllTiles, Platform, Sky, DISPLAY_RATIO, LEVEL_SIZE};
+use crate::world::{AllTiles, Platform, Sky, DISPLAY_RATIO};
 use std::time::Duration;
 
 #[cfg(debug_assertions)]
@@ -62,6 +69,13 @@ impl Default for Background {
     }
 }
 
+/// State of the seed field of the main menu
+#[derive(Resource, Default)]
+struct SeedInput {
+    editing: bool,
+    text: String,
+}
+
 #[derive(Resource)]
 struct ButtonColors {
     normal: BackgroundColor,
@@ -73,9 +87,47 @@ pub struct GameOver;
 #[derive(Component)]
 pub struct MainMenu;
 #[derive(Component)]
+pub struct PlayButton;
+#[derive(Component)]
+pub struct SeedButton;
+#[derive(Component)]
+pub struct PreviousSongButton;
+#[derive(Component)]
+pub struct NextSongButton;
+#[derive(Component)]
+pub struct SongInfoText;
+#[derive(Component)]
+pub struct DifficultyButton;
+#[derive(Component)]
+pub struct DifficultyText;
+#[derive(Component)]
+pub struct ScoresButton;
+#[derive(Component)]
+pub struct ScoresScreen;
+#[derive(Component)]
+pub struct EditorButton;
+#[derive(Component)]
+pub struct PuzzlesButton;
+#[derive(Component)]
+pub struct PracticeButton;
+#[derive(Component)]
+pub struct ControlsButton;
+#[derive(Component)]
+pub struct CalibrationButton;
+#[derive(Component)]
+pub struct SettingsButton;
+#[derive(Component)]
+pub struct TempoButton;
+#[derive(Component)]
+pub struct TempoText;
+#[derive(Component)]
+pub struct BackButton;
+#[derive(Component)]
 pub struct QuitGame;
 #[derive(Component)]
 pub struct Retry;
+#[derive(Component)]
+pub struct BackToMenu;
 /// This plugin is responsible for the game menu (containing only one button...)
 #[derive(Component)]
 pub struct BackgroundTag;
@@ -84,28 +136,82 @@ pub struct BackgroundTag;
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<ButtonColors>()
+            .init_resource::<SeedInput>()
             .add_systems(OnEnter(GameState::Menu), setup_menu)
             .add_systems(Update, click_play_button.run_if(in_state(GameState::Menu)))
+            .add_systems(Update, edit_seed.run_if(in_state(GameState::Menu)))
             .add_systems(
                 Update,
-                change_button_color_on_hover
-                    .run_if(in_state(GameState::Menu).or_else(in_state(GameState::GameOver))),
+                (pick_difficulty, pick_tempo, pick_song)
+                    .chain()
+                    .run_if(in_state(GameState::Menu)),
             )
-            // .add_system(click_play_button.in_schedule(OnEnter(GameState::Menu)))
-            .add_systems(OnExit(GameState::Menu), despawn_screen::<MainMenu>)
             .add_systems(
                 Update,
-                (click_play_button).run_if(in_state(GameState::Menu)),
+                click_scores_button.run_if(in_state(GameState::Menu)),
+            )
+            .add_systems(
+                Update,
+                click_editor_button.run_if(in_state(GameState::Menu)),
+            )
+            .add_systems(
+                Update,
+                click_puzzles_button.run_if(in_state(GameState::Menu)),
+            )
+            .add_systems(
+                Update,
+                click_practice_button.run_if(in_state(GameState::Menu)),
+            )
+            .add_systems(
+                Update,
+                click_controls_button.run_if(in_state(GameState::Menu)),
+            )
+            .add_systems(
+                Update,
+                click_calibration_button.run_if(in_state(GameState::Menu)),
+            )
+            .add_systems(
+                Update,
+                click_settings_button.run_if(in_state(GameState::Menu)),
             )
+            .add_systems(Update, click_quit_button.run_if(in_state(GameState::Menu)))
+            .add_systems(OnEnter(GameState::Scores), setup_scores_screen)
             .add_systems(
                 Update,
-                (close_on_esc)
-                    .run_if(in_state(GameState::Menu).or_else(in_state(GameState::Playing))),
+                click_back_button.run_if(
+                    in_state(GameState::Scores)
+                        .or_else(in_state(GameState::Editor))
+                        .or_else(in_state(GameState::LevelSelect))
+                        .or_else(in_state(GameState::Controls))
+                        .or_else(in_state(GameState::Calibration)),
+                ),
             )
+            .add_systems(OnExit(GameState::Scores), despawn_screen::<ScoresScreen>)
+            .add_systems(
+                Update,
+                change_button_color_on_hover.run_if(
+                    in_state(GameState::Menu)
+                        .or_else(in_state(GameState::GameOver))
+                        .or_else(in_state(GameState::Scores))
+                        .or_else(in_state(GameState::Editor))
+                        .or_else(in_state(GameState::LevelSelect))
+                        .or_else(in_state(GameState::Controls))
+                        .or_else(in_state(GameState::Calibration))
+                        .or_else(in_state(GameState::Settings))
+                        .or_else(in_state(PauseState::Paused)),
+                ),
+            )
+            // .add_system(click_play_button.in_schedule(OnEnter(GameState::Menu)))
+            .add_systems(OnExit(GameState::Menu), despawn_screen::<MainMenu>)
+            .add_systems(
+                Update,
+                (click_play_button).run_if(in_state(GameState::Menu)),
+            )
+            .add_systems(Update, (close_on_esc).run_if(in_state(GameState::Menu)))
             .add_systems(
                 OnEnter(GameState::GameOver),
                 (
-                    setup_game_over_screen,
+                    setup_game_over_screen.after(record_high_score),
                     game_over_clear,
                     highlight_blocked_loss_condition,
                 ),
@@ -117,6 +223,13 @@ impl Plugin for MenuPlugin {
                     .run_if(in_state(GameState::GameOver)),
             )
             .add_systems(OnEnter(GameState::Menu), spawn_background);
+        // Leaving a run from the pause menu:
+        for (from, to) in LEAVE_RUN_TRANSITIONS {
+            app.add_systems(
+                OnTransition { from, to },
+                (game_over_clear, exit_game_over_menu),
+            );
+        }
 
         #[cfg(debug_assertions)]
         {
@@ -174,6 +287,7 @@ fn setup_menu(
             ..Default::default()
         })
         .insert(MainMenu)
+        .insert(PlayButton)
         .with_children(|parent| {
             parent.spawn(TextBundle {
                 text: Text {
@@ -191,6 +305,170 @@ fn setup_menu(
                 ..Default::default()
             });
         });
+    let style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 30.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                bottom: Val::Percent(5.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(MainMenu)
+        .with_children(|parent| {
+            spawn_menu_button(parent, &style, "Settings", SettingsButton);
+            spawn_menu_button(parent, &style, "Calibration", CalibrationButton);
+            spawn_menu_button(parent, &style, "Controls", ControlsButton);
+            spawn_menu_button(parent, &style, "Practice", PracticeButton);
+            spawn_menu_button(parent, &style, "Puzzles", PuzzlesButton);
+            spawn_menu_button(parent, &style, "Beatmap editor", EditorButton);
+            spawn_menu_button(parent, &style, "Scores", ScoresButton);
+            spawn_menu_button(parent, &style, "Seed: random", SeedButton);
+        });
+    commands
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(120.0),
+                height: Val::Px(50.0),
+                position_type: PositionType::Absolute,
+                right: Val::Percent(5.),
+                bottom: Val::Percent(5.),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(MainMenu)
+        .insert(QuitGame)
+        .with_children(|parent| {
+            parent.spawn(TextBundle {
+                text: Text {
+                    sections: vec![TextSection {
+                        value: "Exit".to_string(),
+                        style: TextStyle {
+                            font: font_assets.fira_sans.clone(),
+                            font_size: 30.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    }],
+                    alignment: TextAlignment::Center,
+                    linebreak_behavior: BreakLineOn::WordBoundary,
+                },
+                ..Default::default()
+            });
+        });
+    // Song picker, on the left of the instructions:
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Percent(3.),
+                top: Val::Percent(30.),
+                width: Val::Px(300.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.6)),
+            ..default()
+        })
+        .insert(MainMenu)
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        justify_content: JustifyContent::SpaceBetween,
+                        width: Val::Percent(100.),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_arrow_button(parent, &font_assets, "<", PreviousSongButton);
+                    spawn_arrow_button(parent, &font_assets, ">", NextSongButton);
+                });
+            parent
+                .spawn(TextBundle {
+                    text: Text {
+                        sections: vec![TextSection {
+                            value: String::new(),
+                            style: TextStyle {
+                                font: font_assets.fira_sans.clone(),
+                                font_size: 26.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                            },
+                        }],
+                        alignment: TextAlignment::Center,
+                        linebreak_behavior: BreakLineOn::WordBoundary,
+                    },
+                    style: Style {
+                        margin: UiRect::all(Val::Px(10.)),
+                        ..default()
+                    },
+                    ..Default::default()
+                })
+                .insert(SongInfoText);
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(240.0),
+                        height: Val::Px(50.0),
+                        margin: UiRect::bottom(Val::Px(10.)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(DifficultyButton)
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font: font_assets.fira_sans.clone(),
+                                font_size: 26.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                            },
+                        ))
+                        .insert(DifficultyText);
+                });
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(240.0),
+                        height: Val::Px(50.0),
+                        margin: UiRect::bottom(Val::Px(10.)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(TempoButton)
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font: font_assets.fira_sans.clone(),
+                                font_size: 26.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                            },
+                        ))
+                        .insert(TempoText);
+                });
+        });
     commands
         .spawn(SpriteBundle {
             texture: asset_server.load("textures/instructions.drawio.png"),
@@ -200,6 +478,61 @@ fn setup_menu(
         .insert(MainMenu);
 }
 
+/// A button of the menus, the screens reached from them and the pause menu
+pub fn spawn_menu_button(
+    parent: &mut ChildBuilder,
+    style: &TextStyle,
+    label: &str,
+    marker: impl Component,
+) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(240.0),
+                height: Val::Px(50.0),
+                margin: UiRect::bottom(Val::Px(10.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(marker)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, style.clone()));
+        });
+}
+
+fn spawn_arrow_button(
+    parent: &mut ChildBuilder,
+    font_assets: &FontAssets,
+    label: &str,
+    marker: impl Component,
+) {
+    parent
+        .spawn(ButtonBundle {
+            style: Style {
+                width: Val::Px(50.0),
+                height: Val::Px(50.0),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(marker)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 40.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ));
+        });
+}
+
 #[allow(clippy::type_complexity)]
 fn change_button_color_on_hover(
     button_colors: Res<ButtonColors>,
@@ -223,16 +556,311 @@ fn change_button_color_on_hover(
 
 #[allow(clippy::type_complexity)]
 fn click_play_button(
+    mut game_mode: ResMut<GameMode>,
     mut next_state: ResMut<NextState<GameState>>,
-    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<MainMenu>)>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<PlayButton>)>,
 ) {
     for (interaction,) in &mut interaction_query {
         if let Interaction::Pressed = *interaction {
+            *game_mode = GameMode::Endless;
             next_state.set(GameState::Playing)
         }
     }
 }
 
+#[allow(clippy::type_complexity)]
+fn click_practice_button(
+    mut game_mode: ResMut<GameMode>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<PracticeButton>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            *game_mode = GameMode::Practice;
+            next_state.set(GameState::Playing)
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_scores_button(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<ScoresButton>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            next_state.set(GameState::Scores)
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_editor_button(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<EditorButton>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            next_state.set(GameState::Editor)
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_puzzles_button(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<PuzzlesButton>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            next_state.set(GameState::LevelSelect)
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_controls_button(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<ControlsButton>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            next_state.set(GameState::Controls)
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_calibration_button(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<CalibrationButton>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            next_state.set(GameState::Calibration)
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_settings_button(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<SettingsButton>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            next_state.set(GameState::Settings)
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_quit_button(
+    mut exit: EventWriter<AppExit>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<QuitGame>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            exit.send(AppExit);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn click_back_button(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<BackButton>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            next_state.set(GameState::Menu)
+        }
+    }
+}
+
+/// Clicking the difficulty button cycles through the levels
+#[allow(clippy::type_complexity)]
+fn pick_difficulty(
+    mut difficulty: ResMut<Difficulty>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<DifficultyButton>)>,
+    mut text_query: Query<&mut Text, With<DifficultyText>>,
+) {
+    if interaction_query.iter().any(|x| *x == Interaction::Pressed) {
+        *difficulty = Difficulty::preset(difficulty.level.next());
+    }
+    for mut text in &mut text_query {
+        text.sections[0].value = format!("Difficulty: {:?}", difficulty.level);
+    }
+}
+
+/// Clicking the tempo button cycles through the rates of the practice runs
+#[allow(clippy::type_complexity)]
+fn pick_tempo(
+    mut practice: ResMut<PracticeSettings>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<TempoButton>)>,
+    mut text_query: Query<&mut Text, With<TempoText>>,
+) {
+    if interaction_query.iter().any(|x| *x == Interaction::Pressed) {
+        practice.playback_rate = practice.next_rate();
+    }
+    for mut text in &mut text_query {
+        text.sections[0].value = format!("Practice tempo: {:.1}x", practice.playback_rate);
+    }
+}
+
+/// The arrows cycle through the songs, sorted by id. The picked song is the
+/// one played in the next runs.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn pick_song(
+    audio_assets: Res<AudioAssets>,
+    songs: Res<Assets<SongDefinition>>,
+    high_scores: Res<HighScores>,
+    difficulty: Res<Difficulty>,
+    mut selected_song: ResMut<SelectedSong>,
+    previous_query: Query<&Interaction, (Changed<Interaction>, With<PreviousSongButton>)>,
+    next_query: Query<&Interaction, (Changed<Interaction>, With<NextSongButton>)>,
+    mut text_query: Query<&mut Text, With<SongInfoText>>,
+) {
+    let handles = sorted_songs(&audio_assets, &songs);
+    if handles.is_empty() {
+        return;
+    }
+    let mut offset = 0;
+    if previous_query.iter().any(|x| *x == Interaction::Pressed) {
+        offset = handles.len() - 1;
+    }
+    if next_query.iter().any(|x| *x == Interaction::Pressed) {
+        offset = 1;
+    }
+    if offset != 0 {
+        let ndx = handles
+            .iter()
+            .position(|x| *x == selected_song.handle)
+            .unwrap_or_default();
+        selected_song.handle = handles[(ndx + offset) % handles.len()].clone();
+    }
+
+    let Some(song) = songs.get(&selected_song.handle) else {
+        return;
+    };
+    let best = high_scores
+        .table(&song.id, difficulty.level)
+        .and_then(|x| x.entries.first())
+        .map(|x| x.score.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    for mut text in &mut text_query {
+        text.sections[0].value = format!(
+            "{}\n{}\n{} BPM\nBest: {}",
+            song.name, song.artist, song.bpm, best
+        );
+    }
+}
+
+/// Show the table of every song played so far
+fn setup_scores_screen(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    high_scores: Res<HighScores>,
+    audio_assets: Res<AudioAssets>,
+    songs: Res<Assets<SongDefinition>>,
+) {
+    let style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 30.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(ScoresScreen)
+        .with_children(|parent| {
+            if high_scores.tables.is_empty() {
+                spawn_score_table(
+                    parent,
+                    font_assets.fira_sans.clone(),
+                    "Scores".to_string(),
+                    None,
+                    None,
+                );
+            }
+            for table in high_scores.tables.iter() {
+                // The song may have been removed since:
+                let name = find_song(&audio_assets, &songs, &table.song)
+                    .and_then(|x| songs.get(&x))
+                    .map(|x| x.name.clone())
+                    .unwrap_or_else(|| table.song.clone());
+                let title = format!("{} - {:?}", name, table.difficulty);
+                spawn_score_table(
+                    parent,
+                    font_assets.fira_sans.clone(),
+                    title,
+                    Some(table),
+                    None,
+                );
+            }
+            spawn_menu_button(parent, &style, "Back", BackButton);
+        });
+}
+
+/// Clicking the seed button lets the player type the seed of the next runs.
+/// An empty field means a random seed for every run.
+#[allow(clippy::type_complexity)]
+fn edit_seed(
+    mut seed_input: ResMut<SeedInput>,
+    mut game_seed: ResMut<GameSeed>,
+    mut characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<SeedButton>)>,
+    button_query: Query<&Children, With<SeedButton>>,
+    mut text_query: Query<&mut Text>,
+) {
+    for interaction in &interaction_query {
+        if let Interaction::Pressed = *interaction {
+            seed_input.editing = !seed_input.editing;
+            seed_input.text = game_seed.seed.map(|x| x.to_string()).unwrap_or_default();
+        }
+    }
+
+    if seed_input.editing {
+        for event in characters.iter() {
+            if !event.char.is_ascii_digit() {
+                continue;
+            }
+            // Up to ten digits, as long as the seed fits in a u32:
+            let text = format!("{}{}", seed_input.text, event.char);
+            if text.len() <= 10 && text.parse::<u32>().is_ok() {
+                seed_input.text = text;
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::Back) {
+            seed_input.text.pop();
+        }
+        if keyboard_input.just_pressed(KeyCode::Return) {
+            seed_input.editing = false;
+        }
+        game_seed.seed = seed_input.text.parse().ok();
+    } else {
+        characters.clear();
+    }
+
+    // The label is the only child of the button:
+    let mut labels = text_query.iter_many_mut(button_query.iter().flat_map(|x| x.iter()));
+    while let Some(mut text) = labels.fetch_next() {
+        text.sections[0].value = match (seed_input.editing, game_seed.seed) {
+            (true, _) => format!("Seed: {}_", seed_input.text),
+            (false, Some(seed)) => format!("Seed: {}", seed),
+            (false, None) => "Seed: random".to_string(),
+        };
+    }
+}
+
 #[cfg(debug_assertions)]
 fn debug_start_auto(
     time: Res<Time>,
@@ -259,11 +887,50 @@ fn debug_auto_loss(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn setup_game_over_screen(
     mut commands: Commands,
     // button_colors: Res<ButtonColors>,
     font_assets: Res<FontAssets>,
+    game_board: Res<GameBoard>,
+    score: Res<Score>,
+    current_song: CurrentSong,
+    difficulty: Res<Difficulty>,
+    high_scores: Res<HighScores>,
+    last_rank: Res<LastRank>,
+    game_mode: Res<GameMode>,
 ) {
+    // The table of the song, with the run which just ended highlighted. The
+    // puzzles show their own result instead, and practice runs are not ranked:
+    if *game_mode == GameMode::Endless {
+        commands
+            .spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    top: Val::Percent(20.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            })
+            .insert(GameOver)
+            .with_children(|parent| {
+                let title = match last_rank.rank {
+                    Some(0) => "New record!".to_string(),
+                    _ => format!("{} - {:?}", current_song.get().name, difficulty.level),
+                };
+                spawn_score_table(
+                    parent,
+                    font_assets.fira_sans.clone(),
+                    title,
+                    high_scores.table(&current_song.get().id, difficulty.level),
+                    last_rank.rank,
+                );
+            });
+    }
+
     // Spawn a node containing all the menu:
     commands
         .spawn((NodeBundle {
@@ -325,11 +992,11 @@ fn setup_game_over_screen(
                     },));
                 });
         })
-        // Spawn the Quit button:
+        // Spawn the Menu button:
         .with_children(|parent| {
             parent
                 .spawn((
-                    QuitGame,
+                    BackToMenu,
                     ButtonBundle {
                         style: Style {
                             width: Val::Px(120.0),
@@ -352,7 +1019,7 @@ fn setup_game_over_screen(
                     parent.spawn((TextBundle {
                         text: Text {
                             sections: vec![TextSection {
-                                value: "Quit".to_string(),
+                                value: "Menu".to_string(),
                                 style: TextStyle {
                                     font: font_assets.fira_sans.clone(),
                                     font_size: 40.0,
@@ -365,6 +1032,61 @@ fn setup_game_over_screen(
                         ..Default::default()
                     },));
                 });
+        })
+        // Show the seed to be able to replay the same run:
+        .with_children(|parent| {
+            parent.spawn((TextBundle {
+                text: Text {
+                    sections: vec![TextSection {
+                        value: format!("Seed: {}", game_board.rng.seed()),
+                        style: TextStyle {
+                            font: font_assets.fira_sans.clone(),
+                            font_size: 30.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    }],
+                    alignment: TextAlignment::Center,
+                    linebreak_behavior: BreakLineOn::WordBoundary,
+                },
+                style: Style {
+                    margin: UiRect::horizontal(Val::Px(50.)),
+                    ..default()
+                },
+                ..Default::default()
+            },));
+        })
+        .with_children(|parent| {
+            parent.spawn((TextBundle {
+                text: Text {
+                    sections: vec![TextSection {
+                        value: format!(
+                            "Score: {}\nSwept: {}  Chains: {}  Best combo: {}\n\
+                            Perfect: {}  Good: {}  Late: {}  Miss: {}  Accuracy: {:.0}%",
+                            score.points,
+                            score.swept,
+                            score.chains,
+                            score.max_combo,
+                            score.accuracy.perfect,
+                            score.accuracy.good,
+                            score.accuracy.late,
+                            score.accuracy.miss,
+                            score.accuracy.percent()
+                        ),
+                        style: TextStyle {
+                            font: font_assets.fira_sans.clone(),
+                            font_size: 30.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                    }],
+                    alignment: TextAlignment::Center,
+                    linebreak_behavior: BreakLineOn::WordBoundary,
+                },
+                style: Style {
+                    margin: UiRect::horizontal(Val::Px(50.)),
+                    ..default()
+                },
+                ..Default::default()
+            },));
         });
 }
 
@@ -380,7 +1102,7 @@ fn setup_game_over_screen(
 //// - let the move_cloud system finish (just don't update the grid!)
 //// - bug with resetting the tiles
 //// - remove background when restarting (now there are 2 entities)
-// - In the gameover menu, quit=return to main menu, retry=replay instantly
+//// - In the gameover menu, quit=return to main menu, retry=replay instantly
 // - use the new version of the package for the bevy_ecs_tilemap
 // Add Splashscreen
 // Add credits
@@ -390,7 +1112,9 @@ fn highlight_blocked_loss_condition(
     mut commands: Commands,
     mut query: Query<(&mut Sprite, &mut Transform, Entity), (With<LossCause>,)>,
     mut tile_query: Query<(&TilePos, &mut MessTile)>,
+    game_board: Res<GameBoard>,
 ) {
+    let height = game_board.geometry().height();
     for (sprite, transfo, entity) in query.iter_mut() {
         let mut orig_sprite = sprite.clone();
         orig_sprite.custom_size = Some(Vec2::new(TILE_SIZE, TILE_SIZE));
@@ -423,7 +1147,7 @@ fn highlight_blocked_loss_condition(
     // Add an offset to the timer to make it sliding
     for (pos, mut tile) in tile_query.iter_mut() {
         tile.blink_loss.set_elapsed(Duration::from_secs_f32(
-            ((LEVEL_SIZE - pos.y - 1) as f32 * GAMEOVER_MESS_BLINK_DURATION / 10.)
+            ((height - pos.y - 1) as f32 * GAMEOVER_MESS_BLINK_DURATION / height as f32)
                 % GAMEOVER_MESS_BLINK_DURATION,
         ))
     }
@@ -452,37 +1176,40 @@ fn game_over_screen_interactions(
             Changed<Interaction>,
             With<Button>,
             With<Retry>,
-            Without<QuitGame>,
+            Without<BackToMenu>,
         ),
     >,
     mut next_state: ResMut<NextState<GameState>>,
 
-    mut quit_query: Query<
+    mut menu_query: Query<
         (&Interaction,),
         (
             Changed<Interaction>,
             With<Button>,
-            With<QuitGame>,
+            With<BackToMenu>,
             Without<Retry>,
         ),
     >,
-    mut exit: EventWriter<AppExit>,
 ) {
     for (interaction,) in &mut retry_query {
         if let Interaction::Pressed = *interaction {
             next_state.set(GameState::PreRetry)
         }
     }
-    for (interaction,) in &mut quit_query {
+    // The board is torn down when leaving the game over screen, and the menu
+    // set up again with its background and song preview:
+    for (interaction,) in &mut menu_query {
         if let Interaction::Pressed = *interaction {
-            exit.send(AppExit);
+            next_state.set(GameState::Menu)
         }
     }
 }
 
+/// Only the song is faded out, the game over stinger being played on the
+/// channel of the sound effects
 #[allow(clippy::type_complexity)]
-fn game_over_clear(audio: Res<Audio>) {
-    audio.stop().fade_out(AudioTween::new(
+fn game_over_clear(music: Res<AudioChannel<MusicChannel>>) {
+    music.stop().fade_out(AudioTween::new(
         Duration::from_secs(1),
         AudioEasing::InOutPowi(2),
     ));
@@ -491,7 +1218,18 @@ fn game_over_clear(audio: Res<Audio>) {
 #[allow(clippy::type_complexity)]
 fn exit_game_over_menu(
     mut commands: Commands,
-    mut query: Query<Entity, Or<(With<Cloud>, With<Player>, With<GameOver>, With<MessBar>)>>,
+    mut query: Query<
+        Entity,
+        Or<(
+            With<Cloud>,
+            With<Player>,
+            With<GameOver>,
+            With<MessBar>,
+            With<ScoreText>,
+            With<LevelBanner>,
+            With<JudgementLabel>,
+        )>,
+    >,
     mut tile_storage_query: Query<(&mut TileStorage, Entity), With<TileStorage>>,
     mut tile_query: Query<&mut TilePos, With<AllTiles>>,
 ) {
@@ -607,7 +1345,7 @@ fn spawn_background(
 }
 
 // Generic system that takes a component as a parameter, and will despawn all entities with that component
-fn despawn_screen<T: Component>(to_despawn: Query<Entity, With<T>>, mut commands: Commands) {
+pub fn despawn_screen<T: Component>(to_despawn: Query<Entity, With<T>>, mut commands: Commands) {
     for entity in &to_despawn {
         commands.entity(entity).despawn_recursive();
     }
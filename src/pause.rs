@@ -0,0 +1,206 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Pause of the runs, with Escape or when the window loses the focus. The run
+//! stays in `GameState::Playing`, so that it is not set up again on resume:
+//! the clock and the song are frozen instead, and the inputs ignored. On
+//! resume, the song is moved back to the position of the clock.
+
+use crate::audio::SongHandle;
+use crate::loading::FontAssets;
+use crate::logic::MainClock;
+use crate::menu::{despawn_screen, spawn_menu_button};
+use crate::replay::ReplayPlayback;
+use crate::score::SCORE_LAYER;
+use crate::settings::spawn_settings_panel;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::window::WindowFocused;
+use bevy_kira_audio::prelude::*;
+
+pub const PAUSE_KEY: KeyCode = KeyCode::Escape;
+pub const PAUSE_FONT_SIZE: f32 = 40.;
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const OVERLAY_COLOR: Color = Color::rgba(0., 0., 0., 0.6);
+
+/// The transitions leaving a run from the pause menu, which do not go
+/// through the game over screen
+pub const LEAVE_RUN_TRANSITIONS: [(GameState, GameState); 2] = [
+    (GameState::Playing, GameState::PreRetry),
+    (GameState::Playing, GameState::Menu),
+];
+
+pub struct PausePlugin;
+
+/// Whether the run being played is paused, only relevant in
+/// `GameState::Playing`
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+}
+
+#[derive(Component)]
+struct PauseMenu;
+#[derive(Component)]
+struct ResumeButton;
+#[derive(Component)]
+struct SettingsButton;
+#[derive(Component)]
+struct RestartButton;
+#[derive(Component)]
+struct MainMenuButton;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<PauseState>()
+            .add_systems(OnExit(GameState::Playing), unpause)
+            .add_systems(
+                Update,
+                (toggle_pause, pause_on_focus_lost).run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                OnEnter(PauseState::Paused),
+                (freeze_run, spawn_pause_menu).run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                OnExit(PauseState::Paused),
+                (
+                    despawn_screen::<PauseMenu>,
+                    resume_run.run_if(in_state(GameState::Playing)),
+                ),
+            )
+            .add_systems(
+                Update,
+                click_pause_buttons
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(in_state(PauseState::Paused)),
+            );
+    }
+}
+
+fn unpause(
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+) {
+    if *pause_state.get() == PauseState::Paused {
+        next_pause_state.set(PauseState::Running);
+    }
+}
+
+fn toggle_pause(
+    keyboard_input: Res<Input<KeyCode>>,
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+) {
+    if !keyboard_input.just_pressed(PAUSE_KEY) {
+        return;
+    }
+    next_pause_state.set(match pause_state.get() {
+        PauseState::Running => PauseState::Paused,
+        PauseState::Paused => PauseState::Running,
+    });
+}
+
+fn pause_on_focus_lost(
+    mut focus_events: EventReader<WindowFocused>,
+    pause_state: Res<State<PauseState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+) {
+    let focus_lost = focus_events.iter().any(|x| !x.focused);
+    if focus_lost && *pause_state.get() == PauseState::Running {
+        next_pause_state.set(PauseState::Paused);
+    }
+}
+
+fn freeze_run(
+    mut main_clock: ResMut<MainClock>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    handle: Res<SongHandle>,
+) {
+    main_clock.paused = true;
+    if let Some(instance) = audio_instances.get_mut(&handle.song) {
+        instance.pause(AudioTween::default());
+    }
+}
+
+/// The song starts again where the clock stopped, rather than where it was
+/// paused, so that the correction of their drift starts from zero. A paused
+/// replay stays paused.
+fn resume_run(
+    mut main_clock: ResMut<MainClock>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    handle: Res<SongHandle>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    if playback.is_some_and(|x| x.paused) {
+        return;
+    }
+    main_clock.paused = false;
+    main_clock.excess_time = 0.;
+    if let Some(instance) = audio_instances.get_mut(&handle.song) {
+        instance.seek_to(main_clock.absolute_timer.elapsed_secs() as f64);
+        instance.resume(AudioTween::default());
+    }
+}
+
+fn spawn_pause_menu(mut commands: Commands, font_assets: Res<FontAssets>) {
+    let style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: PAUSE_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: OVERLAY_COLOR.into(),
+            z_index: ZIndex::Global(SCORE_LAYER as i32),
+            ..default()
+        })
+        .insert(PauseMenu)
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section("Paused", style.clone()).with_style(Style {
+                    margin: UiRect::all(Val::Px(20.)),
+                    ..default()
+                }),
+            );
+            spawn_menu_button(parent, &style, "Resume", ResumeButton);
+            spawn_menu_button(parent, &style, "Settings", SettingsButton);
+            spawn_menu_button(parent, &style, "Restart", RestartButton);
+            spawn_menu_button(parent, &style, "Main menu", MainMenuButton);
+        });
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn click_pause_buttons(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+    resume_query: Query<&Interaction, (Changed<Interaction>, With<ResumeButton>)>,
+    settings_query: Query<&Interaction, (Changed<Interaction>, With<SettingsButton>)>,
+    restart_query: Query<&Interaction, (Changed<Interaction>, With<RestartButton>)>,
+    main_menu_query: Query<&Interaction, (Changed<Interaction>, With<MainMenuButton>)>,
+) {
+    if resume_query.iter().any(|x| *x == Interaction::Pressed) {
+        next_pause_state.set(PauseState::Running);
+    }
+    if settings_query.iter().any(|x| *x == Interaction::Pressed) {
+        spawn_settings_panel(&mut commands, &font_assets);
+    }
+    if restart_query.iter().any(|x| *x == Interaction::Pressed) {
+        next_state.set(GameState::PreRetry);
+    }
+    if main_menu_query.iter().any(|x| *x == Interaction::Pressed) {
+        next_state.set(GameState::Menu);
+    }
+}
@@ -2,12 +2,13 @@
 
 use crate::actions::{Actions, GameControl};
 use crate::audio::{SoundOnAction, SoundOnMove};
+use crate::board::BoardEvent;
 use crate::clouds::{Animation, AnimationState, CloudDir};
 use crate::loading::TextureAssets;
-use crate::logic::{
-    CloudControl, GridState, PushState, TileOccupation, MAX_BUFFER_INPUT, SPECIAL_TIMEOUT,
-};
-use crate::world::{STAGE_BL, STAGE_UR};
+use crate::logic::{BoardUpdate, GameBoard, InputTiming, MainClock, MAX_BUFFER_INPUT};
+use crate::replay::ReplayRecorder;
+use crate::score::Score;
+use crate::world::{tile_offset, Stage};
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_easings::*;
@@ -16,7 +17,6 @@ use colored::*;
 
 pub const TILE_SIZE: f32 = 16.;
 pub const PLAYER_LAYER: f32 = 10.;
-pub const INIT_POS: [i8; 2] = [5i8, 5i8];
 pub const BUFFER_SIZE: usize = 2;
 pub const PLAYER_EASING: bevy_easings::EaseFunction = bevy_easings::EaseFunction::QuadraticIn;
 // Duration of the easing for the clouds in ms:
@@ -30,14 +30,35 @@ pub struct PlayerPlugin;
 #[derive(Default, Resource)]
 pub struct PlayerControl {
     pub input_buffer: [GameControl; MAX_BUFFER_INPUT],
-    pub special_control: u8,
+    /// Timing of each input of the buffer, taken when it was played. None for
+    /// the idle ones and the ones of a replay recorded without timings.
+    pub input_timings: [Option<InputTiming>; MAX_BUFFER_INPUT],
     pub player_pos: [i8; BUFFER_SIZE],
-    pub input_timer: Timer,
-    pub special_timeout: u8,
     pub animation: AnimationState,
     pub sound_counter: u8,
 }
 
+impl PlayerControl {
+    fn buffer_input(&mut self, ndx: usize, timing: InputTiming) {
+        self.input_buffer[ndx] = timing.control;
+        self.input_timings[ndx] = Some(timing);
+    }
+
+    pub fn clear_buffer(&mut self) {
+        self.input_buffer = [GameControl::Idle; MAX_BUFFER_INPUT];
+        self.input_timings = [None; MAX_BUFFER_INPUT];
+    }
+
+    /// Take the oldest input out of the buffer, with its timing
+    fn pop_input(&mut self) -> (GameControl, Option<InputTiming>) {
+        let input = (self.input_buffer[0], self.input_timings[0].take());
+        self.input_buffer[0] = GameControl::Idle;
+        self.input_buffer.rotate_left(1);
+        self.input_timings.rotate_left(1);
+        input
+    }
+}
+
 #[derive(Component, Default)]
 pub struct Player {
     pub pos: Vec2,
@@ -82,9 +103,20 @@ fn animate_sprite(
     }
 }
 
-/// Add all the actions (moves) to the buffer whose elements are going to be popped
-pub fn fill_player_buffer(mut actions: ResMut<Actions>, mut player_control: ResMut<PlayerControl>) {
+/// Add all the actions (moves) to the buffer whose elements are going to be popped.
+/// The input is timed when it is played, but only judged once popped: the ones
+/// dropped from the buffer do not count.
+pub fn fill_player_buffer(
+    mut actions: ResMut<Actions>,
+    mut player_control: ResMut<PlayerControl>,
+    main_clock: Res<MainClock>,
+) {
     let game_control = actions.next_action;
+    let timing = InputTiming {
+        control: game_control,
+        offset: main_clock.beat_offset(),
+        judgement: main_clock.judge(),
+    };
     let idle_ndx = player_control
         .input_buffer
         .iter()
@@ -100,18 +132,16 @@ pub fn fill_player_buffer(mut actions: ResMut<Actions>, mut player_control: ResM
             match game_control {
                 GameControl::Idle => {}
                 GameControl::Up | GameControl::Down | GameControl::Left | GameControl::Right => {
-                    // reset the special buffer every time a direction is played:
-                    player_control.special_control = 0;
                     match special_ndx {
                         Some(_y) => {
                             // Reset the buffer, it forces
-                            player_control.input_buffer = [GameControl::Idle; BUFFER_SIZE];
-                            player_control.input_buffer[0] = game_control;
+                            player_control.clear_buffer();
+                            player_control.buffer_input(0, timing);
                         }
-                        _ => player_control.input_buffer[x] = game_control,
+                        _ => player_control.buffer_input(x, timing),
                     }
                 }
-                GameControl::Special => player_control.input_buffer[x] = GameControl::Special,
+                GameControl::Special => player_control.buffer_input(x, timing),
             }
         }
         // The buffer is full, replace the last element:
@@ -120,17 +150,16 @@ pub fn fill_player_buffer(mut actions: ResMut<Actions>, mut player_control: ResM
             match game_control {
                 GameControl::Idle => {}
                 GameControl::Up | GameControl::Down | GameControl::Left | GameControl::Right => {
-                    player_control.special_control = 0;
                     match special_ndx {
                         Some(_y) => {
                             // Reset the buffer, it forces
-                            player_control.input_buffer = [GameControl::Idle; BUFFER_SIZE];
-                            player_control.input_buffer[0] = game_control;
+                            player_control.clear_buffer();
+                            player_control.buffer_input(0, timing);
                         }
-                        _ => player_control.input_buffer[n] = game_control,
+                        _ => player_control.buffer_input(n, timing),
                     }
                 }
-                GameControl::Special => player_control.input_buffer[n] = GameControl::Special,
+                GameControl::Special => player_control.buffer_input(n, timing),
             }
         }
     };
@@ -141,13 +170,13 @@ pub fn move_player(
     mut commands: Commands,
     mut player_query: Query<(&Transform, Entity), With<Player>>,
     mut player_control: ResMut<PlayerControl>,
+    game_board: Res<GameBoard>,
 ) {
-    let pl_grid_pos = player_control.player_pos;
+    let offset = tile_offset(game_board.geometry(), player_control.player_pos);
     for (transform, entity) in player_query.iter_mut() {
         let new_pos = Vec3::new(
-            (f32::from(pl_grid_pos[0]) - INIT_POS[0] as f32) * TILE_SIZE + TILE_SIZE / 2.
-                - TILE_SIZE / 2.,
-            (f32::from(pl_grid_pos[1]) - INIT_POS[1] as f32) * TILE_SIZE + TILE_SIZE / 2.,
+            offset.x * TILE_SIZE + TILE_SIZE / 2. - TILE_SIZE / 2.,
+            offset.y * TILE_SIZE + TILE_SIZE / 2.,
             PLAYER_LAYER,
         );
         match player_control.animation {
@@ -191,7 +220,9 @@ fn spawn_player(
     mut commands: Commands,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     asset_server: Res<AssetServer>,
+    stage: Res<Stage>,
 ) {
+    let init_pos = stage.geometry.init_pos;
     let texture_handle = asset_server.load("textures/duck_spritesheet.png");
     let texture_atlas = TextureAtlas::from_grid(
         texture_handle,
@@ -206,8 +237,8 @@ fn spawn_player(
         .spawn(SpriteSheetBundle {
             texture_atlas: texture_atlas_handle,
             transform: Transform::from_xyz(
-                TILE_SIZE * (0.5 + (INIT_POS[0] as f32)),
-                TILE_SIZE * (0.5 + (INIT_POS[1] as f32)),
+                TILE_SIZE * (0.5 + (init_pos[0] as f32)),
+                TILE_SIZE * (0.5 + (init_pos[1] as f32)),
                 PLAYER_LAYER,
             ),
             ..default()
@@ -219,174 +250,67 @@ fn spawn_player(
         )));
 }
 
-/// Pop and applies all the player moves and special when the timer expires
+/// Pop the oldest player move on every tick of the main clock, judge it and
+/// step the board with it
+#[allow(clippy::too_many_arguments)]
 pub fn pop_player_buffer(
-    mut cloud_control: ResMut<CloudControl>,
-    mut grid_state: ResMut<GridState>,
+    main_clock: Res<MainClock>,
+    mut game_board: ResMut<GameBoard>,
     mut player_control: ResMut<PlayerControl>,
-    time: Res<Time>,
+    mut replay_recorder: ResMut<ReplayRecorder>,
+    mut score: ResMut<Score>,
+    mut input_timings: EventWriter<InputTiming>,
+    mut board_update_event: EventWriter<BoardUpdate>,
     mut play_move_sound_event: EventWriter<SoundOnMove>,
     mut play_push_sound_event: EventWriter<SoundOnAction>,
 ) {
-    // timers gotta be ticked, to work
-    player_control.input_timer.tick(time.delta());
-
-    if player_control.input_timer.just_finished() {
-        let player_action = player_control.input_buffer[0];
-        player_control.input_buffer[0] = GameControl::Idle;
-        player_control.input_buffer.rotate_left(1);
+    if !main_clock.main_timer.just_finished() {
+        return;
+    }
+    let (player_action, timing) = player_control.pop_input();
+    if let Some(timing) = timing {
+        score.judge_input(timing.judgement);
+        replay_recorder
+            .timings
+            .push((main_clock.tick, timing.offset, timing.judgement));
+        input_timings.send(timing);
+    }
 
-        let (player_new_pos, action_direction, push_state): ([i8; 2], CloudDir, PushState) =
-            match player_action {
-                GameControl::Down => {
-                    let new_pos = if player_control.player_pos[1] > (STAGE_BL[1] as i8) {
-                        [
-                            player_control.player_pos[0],
-                            player_control.player_pos[1] - 1,
-                        ]
-                    } else {
-                        player_control.player_pos
-                    };
-                    let dir = CloudDir::Down;
-                    (
-                        new_pos,
-                        dir,
-                        grid_state.is_occupied(new_pos, dir, TileOccupation::Player),
-                    )
-                }
-                GameControl::Up => {
-                    let new_pos = if player_control.player_pos[1] < (STAGE_UR[1] as i8) {
-                        [
-                            player_control.player_pos[0],
-                            player_control.player_pos[1] + 1,
-                        ]
-                    } else {
-                        player_control.player_pos
-                    };
-                    let dir = CloudDir::Up;
-                    (
-                        new_pos,
-                        dir,
-                        grid_state.is_occupied(new_pos, dir, TileOccupation::Player),
-                    )
-                }
-                GameControl::Left => {
-                    let new_pos = if player_control.player_pos[0] > (STAGE_BL[0] as i8) {
-                        [
-                            player_control.player_pos[0] - 1,
-                            player_control.player_pos[1],
-                        ]
-                    } else {
-                        player_control.player_pos
-                    };
-                    let dir = CloudDir::Left;
-                    (
-                        new_pos,
-                        dir,
-                        grid_state.is_occupied(new_pos, dir, TileOccupation::Player),
-                    )
-                }
-                GameControl::Right => {
-                    let new_pos = if player_control.player_pos[0] < (STAGE_UR[0] as i8) {
-                        [
-                            player_control.player_pos[0] + 1,
-                            player_control.player_pos[1],
-                        ]
-                    } else {
-                        player_control.player_pos
-                    };
-                    let dir = CloudDir::Right;
-                    (
-                        new_pos,
-                        dir,
-                        grid_state.is_occupied(new_pos, dir, TileOccupation::Player),
-                    )
-                }
-                GameControl::Idle => (player_control.player_pos, CloudDir::Right, PushState::Empty),
-                GameControl::Special => {
-                    player_control.special_control += 1;
-                    player_control.special_timeout = 0;
-                    (
-                        player_control.player_pos,
-                        CloudDir::Down,
-                        PushState::Blocked,
-                    )
-                }
-            };
-        let player_old_pos = player_control.player_pos;
+    let events = game_board.step(player_action);
 
-        if player_action != GameControl::Idle {
-            player_control.sound_counter += 1;
-            player_control.sound_counter %= 4;
-            match push_state {
-                PushState::Empty => {
-                    player_control.player_pos = player_new_pos;
-                    debug!("pl. pos: {:?}", player_control.player_pos);
-                    grid_state.grid[player_old_pos[0] as usize][player_old_pos[1] as usize] =
-                        TileOccupation::Empty;
-                    grid_state.grid[player_new_pos[0] as usize][player_new_pos[1] as usize] =
-                        TileOccupation::Player;
-                    if player_control.sound_counter == 0 {
-                        play_move_sound_event.send_default();
-                    }
-                }
-                PushState::Blocked => {}
-                PushState::CanPush => {
-                    cloud_control
-                        .pushed_clouds
-                        .push((player_old_pos, action_direction));
-                    match action_direction {
-                        CloudDir::Up => {
-                            play_push_sound_event.send(SoundOnAction {
-                                direction: GameControl::Down,
-                            });
-                            cloud_control.next_pushed_clouds.push((
-                                player_new_pos,
-                                action_direction,
-                                PushState::PlayerCanPush,
-                            ))
-                        }
-                        CloudDir::Down => {
-                            play_push_sound_event.send(SoundOnAction {
-                                direction: GameControl::Up,
-                            });
-                            cloud_control.next_pushed_clouds.push((
-                                player_new_pos,
-                                action_direction,
-                                PushState::PlayerCanPush,
-                            ))
-                        }
-                        CloudDir::Left => {
-                            play_push_sound_event.send(SoundOnAction {
-                                direction: GameControl::Right,
-                            });
-                            cloud_control.next_pushed_clouds.push((
-                                player_new_pos,
-                                action_direction,
-                                PushState::PlayerCanPush,
-                            ))
-                        }
-                        CloudDir::Right => {
-                            play_push_sound_event.send(SoundOnAction {
-                                direction: GameControl::Left,
-                            });
-                            cloud_control.next_pushed_clouds.push((
-                                player_new_pos,
-                                action_direction,
-                                PushState::PlayerCanPush,
-                            ))
-                        }
-                    };
-                    play_push_sound_event.send_default();
+    if player_action != GameControl::Idle {
+        replay_recorder
+            .inputs
+            .push((main_clock.tick, player_action));
+        player_control.sound_counter += 1;
+        player_control.sound_counter %= 4;
+    }
+    for event in events {
+        match event {
+            BoardEvent::PlayerMoved { to, pushed, .. } => {
+                player_control.player_pos = to;
+                debug!("pl. pos: {:?}", player_control.player_pos);
+                if !pushed && player_control.sound_counter == 0 {
+                    play_move_sound_event.send_default();
                 }
-                _ => {}
             }
+            BoardEvent::PlayerPush { dir } => {
+                play_push_sound_event.send(SoundOnAction {
+                    direction: match dir {
+                        CloudDir::Up => GameControl::Down,
+                        CloudDir::Down => GameControl::Up,
+                        CloudDir::Left => GameControl::Right,
+                        CloudDir::Right => GameControl::Left,
+                    },
+                });
+            }
+            BoardEvent::Special => {
+                play_push_sound_event.send(SoundOnAction {
+                    direction: GameControl::Special,
+                });
+            }
+            _ => (),
         }
-        player_control.special_timeout += 1;
-        // if the special is not used soon enough, it expires:
-        if player_control.special_timeout >= SPECIAL_TIMEOUT {
-            player_control.special_timeout = 0;
-            player_control.special_control = 0;
-        }
-    };
+        board_update_event.send(BoardUpdate(event));
+    }
 }
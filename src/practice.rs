@@ -0,0 +1,187 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Practice mode: endless runs which cannot be lost, with the song slowed
+//! down to a tempo picked in the menu. The clock follows the song, so the
+//! beats are longer by the same ratio. An overlay lists the last inputs and
+//! how far from the beat they were played.
+
+use std::collections::VecDeque;
+
+use crate::loading::FontAssets;
+use crate::logic::{InputTiming, MainClock};
+use crate::menu::despawn_screen;
+use crate::pause::{PauseState, LEAVE_RUN_TRANSITIONS};
+use crate::player::TILE_SIZE;
+use crate::score::SCORE_LAYER;
+use crate::world::{Stage, DISPLAY_RATIO};
+use crate::{GameMode, GameState};
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+
+/// Playback rates offered in the menu, the song being played at 1
+pub const PRACTICE_RATES: [f32; 6] = [0.5, 0.6, 0.7, 0.8, 0.9, 1.];
+pub const DEFAULT_PRACTICE_RATE: f32 = 0.7;
+pub const END_PRACTICE_KEY: KeyCode = KeyCode::Return;
+pub const PRACTICE_FONT_SIZE: f32 = 24.;
+// Number of inputs listed in the overlay:
+pub const SHOWN_INPUTS: usize = 8;
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const ON_BEAT_COLOR: Color = Color::rgb(0.3, 0.9, 0.3);
+const OFF_BEAT_COLOR: Color = Color::rgb(0.9, 0.3, 0.3);
+
+pub struct PracticePlugin;
+
+/// Tempo of the next practice runs
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct PracticeSettings {
+    pub playback_rate: f32,
+}
+
+impl Default for PracticeSettings {
+    fn default() -> Self {
+        PracticeSettings {
+            playback_rate: DEFAULT_PRACTICE_RATE,
+        }
+    }
+}
+
+impl PracticeSettings {
+    /// The rate after this one in the menu
+    pub fn next_rate(&self) -> f32 {
+        PRACTICE_RATES
+            .iter()
+            .copied()
+            .find(|x| *x > self.playback_rate)
+            .unwrap_or(PRACTICE_RATES[0])
+    }
+
+    /// Rate of the song and of the clock in the given mode, the other modes
+    /// being played at the speed of the song
+    pub fn playback_rate(&self, game_mode: GameMode) -> f32 {
+        match game_mode {
+            GameMode::Practice => self.playback_rate,
+            GameMode::Endless | GameMode::Puzzle(_) => 1.,
+        }
+    }
+}
+
+/// The last inputs of the run, the most recent last
+#[derive(Resource, Default)]
+struct PracticeLog {
+    timings: VecDeque<InputTiming>,
+}
+
+#[derive(Component)]
+struct PracticeText;
+
+impl Plugin for PracticePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PracticeSettings>()
+            .init_resource::<PracticeLog>()
+            .add_systems(
+                OnEnter(GameState::Playing),
+                (clear_log, spawn_practice_text).run_if(in_practice_mode),
+            )
+            .add_systems(
+                Update,
+                update_practice_text
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(in_practice_mode),
+            )
+            .add_systems(
+                Update,
+                end_practice
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(in_state(PauseState::Running))
+                    .run_if(in_practice_mode),
+            )
+            .add_systems(OnExit(GameState::GameOver), despawn_screen::<PracticeText>);
+        for (from, to) in LEAVE_RUN_TRANSITIONS {
+            app.add_systems(OnTransition { from, to }, despawn_screen::<PracticeText>);
+        }
+    }
+}
+
+/// Run condition of the systems only used by the practice runs
+pub fn in_practice_mode(game_mode: Res<GameMode>) -> bool {
+    *game_mode == GameMode::Practice
+}
+
+fn clear_log(mut log: ResMut<PracticeLog>) {
+    log.timings.clear();
+}
+
+/// The overlay is on the left of the stage, the score being on its right
+fn spawn_practice_text(mut commands: Commands, font_assets: Res<FontAssets>, stage: Res<Stage>) {
+    commands.spawn((
+        Text2dBundle {
+            text: Text::default(),
+            text_anchor: Anchor::TopRight,
+            transform: Transform::from_xyz(
+                -((stage.geometry.width() as f32) / 2. + 1.) * TILE_SIZE,
+                ((stage.geometry.height() as f32) / 2.) * TILE_SIZE,
+                SCORE_LAYER,
+            )
+            .with_scale(Vec3::splat(DISPLAY_RATIO)),
+            ..default()
+        },
+        PracticeText,
+    ));
+}
+
+/// One line per input, green when it landed inside the forgiveness margin.
+/// The offsets are in milliseconds of the song, like the margin.
+fn update_practice_text(
+    font_assets: Res<FontAssets>,
+    settings: Res<PracticeSettings>,
+    main_clock: Res<MainClock>,
+    mut input_timings: EventReader<InputTiming>,
+    mut log: ResMut<PracticeLog>,
+    mut query: Query<&mut Text, With<PracticeText>>,
+) {
+    for timing in input_timings.iter() {
+        if log.timings.len() >= SHOWN_INPUTS {
+            log.timings.pop_front();
+        }
+        log.timings.push_back(*timing);
+    }
+    let style = |color| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: PRACTICE_FONT_SIZE,
+        color,
+    };
+    let mut sections = vec![TextSection::new(
+        format!(
+            "Practice {:.1}x\nWindow: {:.0} ms\nEnter: end\nBackspace: rewind\n",
+            settings.playback_rate,
+            main_clock.forgiveness_margin() * 1000.
+        ),
+        style(TEXT_COLOR),
+    )];
+    for timing in log.timings.iter() {
+        let color = if timing.judgement.is_on_beat() {
+            ON_BEAT_COLOR
+        } else {
+            OFF_BEAT_COLOR
+        };
+        sections.push(TextSection::new(
+            format!(
+                "\n{:?} {:+.0} ms {:?}",
+                timing.control,
+                timing.offset * 1000.,
+                timing.judgement
+            ),
+            style(color),
+        ));
+    }
+    for mut text in query.iter_mut() {
+        text.sections = sections.clone();
+    }
+}
+
+/// A practice run only ends when the player wants to
+fn end_practice(keyboard_input: Res<Input<KeyCode>>, mut next_state: ResMut<NextState<GameState>>) {
+    if keyboard_input.just_pressed(END_PRACTICE_KEY) {
+        next_state.set(GameState::GameOver);
+    }
+}
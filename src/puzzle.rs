@@ -0,0 +1,168 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Puzzle levels, independent from Bevy.
+//!
+//! A puzzle starts from a fixed board and no cloud spawns during it: the
+//! player has to clear the clouds already there before running out of moves
+//! or of time. It is drawn like a stage, with the clouds on the platform:
+//!
+//! ```ron
+//! (
+//!     name: "Corner",
+//!     goal: ClearInMoves(3),
+//!     // `<` `>` `^` `v` clouds going left, right, up and down:
+//!     map: [
+//!         "~~~~~~~",
+//!         "~~~~~~~",
+//!         "~~#>X~~",
+//!         "~~#@#~~",
+//!         "~~###~~",
+//!         "~~~~~~~",
+//!         "~~~~~~~",
+//!     ],
+//! )
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{
+    Board, BoardConfig, BoardGeometry, CloudDir, LossCondition, StageTile, BEATS_PER_BAR,
+};
+
+/// What the player has to do to solve a puzzle
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PuzzleGoal {
+    /// Clear every cloud with at most this number of moves
+    ClearInMoves(u32),
+    /// Clear every cloud before this number of bars has been played
+    ClearBeforeBar(u32),
+}
+
+impl PuzzleGoal {
+    /// Short description shown to the player
+    pub fn describe(self) -> String {
+        match self {
+            PuzzleGoal::ClearInMoves(moves) => format!("Clear in {} moves", moves),
+            PuzzleGoal::ClearBeforeBar(bars) => format!("Clear before bar {}", bars),
+        }
+    }
+
+    /// How far the player is from the limit, like `Moves: 2/3`
+    pub fn progress(self, board: &Board) -> String {
+        match self {
+            PuzzleGoal::ClearInMoves(moves) => format!("Moves: {}/{}", board.moves, moves),
+            PuzzleGoal::ClearBeforeBar(bars) => {
+                format!("Bar: {}/{}", board.beat / BEATS_PER_BAR + 1, bars)
+            }
+        }
+    }
+
+    pub fn status(self, board: &Board) -> PuzzleStatus {
+        if board.loss_condition != LossCondition::NoLoss {
+            return PuzzleStatus::Failed;
+        }
+        if board.clouds.is_empty() {
+            return PuzzleStatus::Solved;
+        }
+        let failed = match self {
+            // After the last move, the clouds can still leave by themselves:
+            PuzzleGoal::ClearInMoves(moves) => {
+                board.moves > moves || (board.moves == moves && board.is_settled())
+            }
+            PuzzleGoal::ClearBeforeBar(bars) => board.beat / BEATS_PER_BAR >= bars,
+        };
+        if failed {
+            PuzzleStatus::Failed
+        } else {
+            PuzzleStatus::Playing
+        }
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PuzzleStatus {
+    #[default]
+    Playing,
+    Solved,
+    Failed,
+}
+
+/// Content of a puzzle file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: PuzzleGoal,
+    /// The stage as read by `BoardGeometry::from_map`, with the clouds drawn
+    /// on the platform
+    pub map: Vec<String>,
+}
+
+/// The board of a puzzle before the first move
+#[derive(Debug, Clone, PartialEq)]
+pub struct PuzzleLayout {
+    pub geometry: BoardGeometry,
+    pub clouds: Vec<([i8; 2], CloudDir)>,
+}
+
+impl PuzzleLayout {
+    /// A board in this state, without spawns. Its seed does not matter since
+    /// the only random decisions are the spawns.
+    pub fn board(&self, config: BoardConfig) -> Board {
+        Board::with_config(0, config)
+            .with_geometry(self.geometry.clone())
+            .with_clouds(&self.clouds)
+            .without_spawns()
+    }
+}
+
+/// The character of a cloud in a puzzle map
+pub fn cloud_symbol(dir: CloudDir) -> char {
+    match dir {
+        CloudDir::Up => '^',
+        CloudDir::Down => 'v',
+        CloudDir::Left => '<',
+        CloudDir::Right => '>',
+    }
+}
+
+pub fn cloud_from_symbol(symbol: char) -> Option<CloudDir> {
+    [
+        CloudDir::Up,
+        CloudDir::Down,
+        CloudDir::Left,
+        CloudDir::Right,
+    ]
+    .into_iter()
+    .find(|x| cloud_symbol(*x) == symbol)
+}
+
+impl Puzzle {
+    /// Split the map into the stage and the clouds standing on it
+    pub fn layout(&self) -> Result<PuzzleLayout, String> {
+        let height = self.map.len();
+        let mut clouds = Vec::new();
+        let mut rows = Vec::new();
+        for (row_ndx, row) in self.map.iter().enumerate() {
+            let y = height - 1 - row_ndx;
+            let mut stage_row = String::new();
+            for (x, symbol) in row.chars().enumerate() {
+                match cloud_from_symbol(symbol) {
+                    Some(dir) => {
+                        clouds.push(([x as i8, y as i8], dir));
+                        stage_row.push(StageTile::Platform.symbol());
+                    }
+                    None => stage_row.push(symbol),
+                }
+            }
+            rows.push(stage_row);
+        }
+        let geometry = BoardGeometry::from_map(&rows)?;
+        if clouds.is_empty() {
+            return Err("the puzzle has no cloud".to_string());
+        }
+        if clouds.iter().any(|x| x.0 == geometry.init_pos) {
+            return Err("a cloud is on the start of the player".to_string());
+        }
+        Ok(PuzzleLayout { geometry, clouds })
+    }
+}
@@ -0,0 +1,480 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Puzzle mode: the levels of `assets/puzzles`, see [`crate::puzzle`]. They
+//! are picked on a level select screen reached from the menu, and the ones
+//! solved are saved to `puzzles.ron` in the data directory.
+
+use std::collections::BTreeSet;
+
+use crate::board::LossCondition;
+use crate::loading::{DataAssets, FontAssets};
+use crate::logic::{GameBoard, LogicSystem};
+use crate::menu::{despawn_screen, spawn_menu_button, BackButton, GameOver};
+use crate::pause::LEAVE_RUN_TRANSITIONS;
+use crate::player::TILE_SIZE;
+use crate::puzzle::{Puzzle, PuzzleLayout, PuzzleStatus};
+use crate::save::{data_dir, load_ron, save_ron};
+use crate::score::SCORE_LAYER;
+use crate::world::{load_stage, Stage, StageAsset, DISPLAY_RATIO};
+use crate::{GameMode, GameState};
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::sprite::Anchor;
+use serde::{Deserialize, Serialize};
+
+pub const PUZZLE_EXTENSION: &str = ".puzzle.ron";
+pub const PUZZLE_PROGRESS_FILE: &str = "puzzles.ron";
+pub const PUZZLE_FONT_SIZE: f32 = 30.;
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const SOLVED_COLOR: Color = Color::GOLD;
+
+pub struct PuzzleModePlugin;
+
+/// A puzzle file of `assets/puzzles`, the id being its name without the
+/// extension
+pub struct PuzzleLevel {
+    pub id: String,
+    pub puzzle: Puzzle,
+    pub layout: PuzzleLayout,
+}
+
+/// Content of a `.puzzle.ron` file
+#[derive(TypeUuid, TypePath, Debug)]
+#[uuid = "de27d605-dcda-47e6-a897-a78f03907db0"]
+pub struct PuzzleAsset {
+    /// Name of the file, without the extension
+    pub id: String,
+    pub puzzle: Puzzle,
+}
+
+#[derive(Default)]
+struct PuzzleLoader;
+
+impl AssetLoader for PuzzleLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let puzzle: Puzzle = ron::de::from_bytes(bytes)?;
+            let id = load_context
+                .path()
+                .file_name()
+                .and_then(|x| x.to_str())
+                .and_then(|x| x.strip_suffix(PUZZLE_EXTENSION))
+                .unwrap_or_default()
+                .to_string();
+            load_context.set_default_asset(LoadedAsset::new(PuzzleAsset { id, puzzle }));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["puzzle.ron"]
+    }
+}
+
+/// Every valid puzzle, in the order of their file names
+#[derive(Resource, Default)]
+pub struct Puzzles {
+    pub levels: Vec<PuzzleLevel>,
+}
+
+impl Puzzles {
+    /// The level played in the given mode, if it is a puzzle
+    pub fn current(&self, game_mode: GameMode) -> Option<&PuzzleLevel> {
+        match game_mode {
+            GameMode::Puzzle(ndx) => self.levels.get(ndx),
+            GameMode::Endless | GameMode::Practice => None,
+        }
+    }
+}
+
+/// Ids of the puzzles solved at least once
+#[derive(Resource, Default, Debug, Serialize, Deserialize)]
+pub struct PuzzleProgress {
+    pub solved: BTreeSet<String>,
+}
+
+/// Where the puzzle being played stands
+#[derive(Resource, Default)]
+pub struct PuzzleResult {
+    pub status: PuzzleStatus,
+}
+
+#[derive(Component)]
+struct LevelSelectScreen;
+#[derive(Component)]
+struct PuzzleButton(usize);
+#[derive(Component)]
+struct LevelsButton;
+#[derive(Component)]
+struct PuzzleText;
+
+impl Plugin for PuzzleModePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<PuzzleAsset>()
+            .init_asset_loader::<PuzzleLoader>()
+            .init_resource::<Puzzles>()
+            .insert_resource(load_progress())
+            .add_systems(OnExit(GameState::Loading), load_puzzles)
+            .init_resource::<PuzzleResult>()
+            .add_systems(OnEnter(GameState::Menu), leave_puzzle_mode)
+            .add_systems(OnEnter(GameState::LevelSelect), setup_level_select)
+            .add_systems(
+                Update,
+                click_puzzle_button.run_if(in_state(GameState::LevelSelect)),
+            )
+            .add_systems(
+                OnExit(GameState::LevelSelect),
+                despawn_screen::<LevelSelectScreen>,
+            )
+            .add_systems(
+                OnEnter(GameState::Playing),
+                spawn_puzzle_text.run_if(in_puzzle_mode),
+            )
+            .add_systems(
+                Update,
+                (check_puzzle_goal, update_puzzle_text)
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(in_puzzle_mode)
+                    .after(LogicSystem::CheckLoss),
+            )
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                setup_puzzle_result.run_if(in_puzzle_mode),
+            )
+            .add_systems(
+                Update,
+                click_levels_button.run_if(in_state(GameState::GameOver)),
+            )
+            .add_systems(OnExit(GameState::GameOver), despawn_screen::<PuzzleText>);
+        for (from, to) in LEAVE_RUN_TRANSITIONS {
+            app.add_systems(OnTransition { from, to }, despawn_screen::<PuzzleText>);
+        }
+    }
+}
+
+/// Run condition of the systems only used by the puzzles
+pub fn in_puzzle_mode(game_mode: Res<GameMode>) -> bool {
+    matches!(*game_mode, GameMode::Puzzle(_))
+}
+
+fn load_puzzles(
+    data_assets: Res<DataAssets>,
+    puzzle_assets: Res<Assets<PuzzleAsset>>,
+    mut puzzles: ResMut<Puzzles>,
+) {
+    let mut assets: Vec<&PuzzleAsset> = data_assets
+        .puzzles
+        .iter()
+        .filter_map(|x| puzzle_assets.get(x))
+        .collect();
+    assets.sort_by(|a, b| a.id.cmp(&b.id));
+    puzzles.levels.clear();
+    for asset in assets {
+        match asset.puzzle.layout() {
+            Ok(layout) => puzzles.levels.push(PuzzleLevel {
+                id: asset.id.clone(),
+                puzzle: asset.puzzle.clone(),
+                layout,
+            }),
+            Err(err) => warn!("invalid puzzle {}: {}", asset.id, err),
+        }
+    }
+}
+
+fn load_progress() -> PuzzleProgress {
+    let path = data_dir().join(PUZZLE_PROGRESS_FILE);
+    if path.exists() {
+        load_ron(&path).unwrap_or_else(|err| {
+            error!("could not load the puzzle progress {:?}: {}", path, err);
+            PuzzleProgress::default()
+        })
+    } else {
+        PuzzleProgress::default()
+    }
+}
+
+/// Back in the menu, the next runs are endless ones on the usual stage
+fn leave_puzzle_mode(
+    mut game_mode: ResMut<GameMode>,
+    mut stage: ResMut<Stage>,
+    data_assets: Res<DataAssets>,
+    stages: Res<Assets<StageAsset>>,
+) {
+    if let GameMode::Puzzle(_) = *game_mode {
+        *game_mode = GameMode::Endless;
+        *stage = load_stage(&data_assets, &stages);
+    }
+}
+
+/// One button per puzzle, the solved ones in gold
+fn setup_level_select(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    puzzles: Res<Puzzles>,
+    progress: Res<PuzzleProgress>,
+) {
+    let style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: PUZZLE_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(LevelSelectScreen)
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section("Puzzles", style.clone()).with_style(Style {
+                    margin: UiRect::all(Val::Px(20.)),
+                    ..default()
+                }),
+            );
+            if puzzles.levels.is_empty() {
+                parent.spawn(TextBundle::from_section("No puzzle found", style.clone()));
+            }
+            for (ndx, level) in puzzles.levels.iter().enumerate() {
+                let mut line_style = style.clone();
+                if progress.solved.contains(&level.id) {
+                    line_style.color = SOLVED_COLOR;
+                }
+                parent
+                    .spawn(ButtonBundle {
+                        style: Style {
+                            width: Val::Px(500.0),
+                            height: Val::Px(50.0),
+                            margin: UiRect::bottom(Val::Px(10.)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .insert(PuzzleButton(ndx))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            format!(
+                                "{}. {} - {}",
+                                ndx + 1,
+                                level.puzzle.name,
+                                level.puzzle.goal.describe()
+                            ),
+                            line_style,
+                        ));
+                    });
+            }
+            spawn_menu_button(parent, &style, "Back", BackButton);
+        });
+}
+
+/// The board of the picked puzzle replaces the stage until the menu is shown
+/// again
+#[allow(clippy::type_complexity)]
+fn click_puzzle_button(
+    mut game_mode: ResMut<GameMode>,
+    mut stage: ResMut<Stage>,
+    mut result: ResMut<PuzzleResult>,
+    puzzles: Res<Puzzles>,
+    mut next_state: ResMut<NextState<GameState>>,
+    interaction_query: Query<(&Interaction, &PuzzleButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(level) = puzzles.levels.get(button.0) {
+            *game_mode = GameMode::Puzzle(button.0);
+            stage.geometry = level.layout.geometry.clone();
+            result.status = PuzzleStatus::Playing;
+            next_state.set(GameState::Playing);
+        }
+    }
+}
+
+/// The goal and how close the player is to its limit, below the score
+fn spawn_puzzle_text(mut commands: Commands, font_assets: Res<FontAssets>, stage: Res<Stage>) {
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: PUZZLE_FONT_SIZE,
+                    color: TEXT_COLOR,
+                },
+            ),
+            text_anchor: Anchor::BottomLeft,
+            transform: Transform::from_xyz(
+                ((stage.geometry.width() as f32) / 2. + 1.) * TILE_SIZE,
+                -((stage.geometry.height() as f32) / 2.) * TILE_SIZE,
+                SCORE_LAYER,
+            )
+            .with_scale(Vec3::splat(DISPLAY_RATIO)),
+            ..default()
+        },
+        PuzzleText,
+    ));
+}
+
+fn update_puzzle_text(
+    game_mode: Res<GameMode>,
+    puzzles: Res<Puzzles>,
+    game_board: Res<GameBoard>,
+    mut query: Query<&mut Text, With<PuzzleText>>,
+) {
+    let Some(level) = puzzles.current(*game_mode) else {
+        return;
+    };
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "{}\n{}\n{}\nBackspace: rewind",
+            level.puzzle.name,
+            level.puzzle.goal.describe(),
+            level.puzzle.goal.progress(&game_board)
+        );
+    }
+}
+
+/// End the run once the puzzle is solved or cannot be anymore. A board lost
+/// by the usual rules ends it by itself, once the loss has been shown.
+fn check_puzzle_goal(
+    game_mode: Res<GameMode>,
+    puzzles: Res<Puzzles>,
+    game_board: Res<GameBoard>,
+    mut progress: ResMut<PuzzleProgress>,
+    mut result: ResMut<PuzzleResult>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(level) = puzzles.current(*game_mode) else {
+        return;
+    };
+    result.status = level.puzzle.goal.status(&game_board);
+    match result.status {
+        PuzzleStatus::Solved => {
+            if progress.solved.insert(level.id.clone()) {
+                let path = data_dir().join(PUZZLE_PROGRESS_FILE);
+                if let Err(err) = save_ron(&path, &*progress) {
+                    error!("could not save the puzzle progress {:?}: {}", path, err);
+                }
+            }
+            next_state.set(GameState::GameOver);
+        }
+        PuzzleStatus::Failed if game_board.loss_condition == LossCondition::NoLoss => {
+            next_state.set(GameState::GameOver);
+        }
+        _ => (),
+    }
+}
+
+/// Shown instead of the table of the best runs, with a way back to the list
+/// of puzzles
+fn setup_puzzle_result(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    game_mode: Res<GameMode>,
+    puzzles: Res<Puzzles>,
+    result: Res<PuzzleResult>,
+) {
+    let Some(level) = puzzles.current(*game_mode) else {
+        return;
+    };
+    let style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: PUZZLE_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+    let title = match result.status {
+        PuzzleStatus::Solved => "Solved!",
+        PuzzleStatus::Failed | PuzzleStatus::Playing => "Failed",
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                top: Val::Percent(20.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(GameOver)
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(20.)),
+                        ..default()
+                    },
+                    background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.6)),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        title,
+                        TextStyle {
+                            font_size: 40.0,
+                            color: match result.status {
+                                PuzzleStatus::Solved => SOLVED_COLOR,
+                                _ => TEXT_COLOR,
+                            },
+                            ..style.clone()
+                        },
+                    ));
+                    parent.spawn(TextBundle::from_section(
+                        format!("{} - {}", level.puzzle.name, level.puzzle.goal.describe()),
+                        style.clone(),
+                    ));
+                });
+            parent
+                .spawn((
+                    LevelsButton,
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(120.0),
+                            height: Val::Px(50.0),
+                            margin: UiRect::top(Val::Px(20.)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Levels",
+                        TextStyle {
+                            font_size: 40.0,
+                            ..style.clone()
+                        },
+                    ));
+                });
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn click_levels_button(
+    mut next_state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<(&Interaction,), (Changed<Interaction>, With<LevelsButton>)>,
+) {
+    for (interaction,) in &mut interaction_query {
+        if let Interaction::Pressed = *interaction {
+            next_state.set(GameState::LevelSelect)
+        }
+    }
+}
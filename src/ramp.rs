@@ -0,0 +1,74 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Progressive difficulty within a run, read from `assets/default.ramp.ron`:
+//!
+//! ```ron
+//! #![enable(implicit_some)]
+//! (
+//!     levels: [
+//!         // Reached after 24 bars or 10 clouds swept, whichever comes first:
+//!         (bars: 24, swept: 10, cooldown_lengthening: 1),
+//!         (bars: 48, swept: 25, cooldown_lengthening: 2, spawn_tightening: 1),
+//!     ],
+//! )
+//! ```
+//!
+//! The changes of a level are relative to the rules of the difficulty, and
+//! replace the ones of the previous level. With a beatmap, only the cooldown
+//! changes since the chart places the spawns.
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::BoardConfig;
+
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ramp {
+    /// Reached one after the other
+    pub levels: Vec<RampLevel>,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RampLevel {
+    /// Bars of 4 beats since the start of the run
+    pub bars: Option<u32>,
+    /// Clouds the player swept off the stage, as counted by `Board::swept`
+    pub swept: Option<u32>,
+    /// Removed from the spawn frequency, which stays at least 1
+    pub spawn_tightening: u8,
+    /// Added to the push cooldown, in beats: the pushed clouds clutter the
+    /// stage for longer
+    pub cooldown_lengthening: u8,
+    /// Other borders spawning a cloud at the same time as the one of the beat
+    pub extra_spawns: u8,
+}
+
+impl RampLevel {
+    pub fn is_reached(&self, bars: u32, swept: u32) -> bool {
+        self.bars.is_some_and(|x| bars >= x) || self.swept.is_some_and(|x| swept >= x)
+    }
+
+    /// The rules of the difficulty once the level is reached
+    pub fn apply(&self, config: &BoardConfig) -> BoardConfig {
+        BoardConfig {
+            spawn_frequency: config
+                .spawn_frequency
+                .saturating_sub(self.spawn_tightening)
+                .max(1),
+            push_cooldown: config
+                .push_cooldown
+                .saturating_add(self.cooldown_lengthening),
+            ..*config
+        }
+    }
+}
+
+impl Ramp {
+    /// Number of levels reached, 0 before the first one
+    pub fn level(&self, bars: u32, swept: u32) -> usize {
+        self.levels
+            .iter()
+            .take_while(|x| x.is_reached(bars, swept))
+            .count()
+    }
+}
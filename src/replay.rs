@@ -0,0 +1,350 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+use std::path::PathBuf;
+
+use crate::actions::GameControl;
+use crate::audio::SongHandle;
+use crate::board::{Board, BoardGeometry, LossCondition, TIMER_SCALE_FACTOR};
+use crate::clouds::{spawn_cloud, AnimationState, Cloud};
+use crate::difficulty::{Difficulty, RampCurve};
+use crate::loading::{AudioAssets, FontAssets};
+use crate::logic::{GameBoard, GameSeed, InputTiming, LogicSystem, MainClock};
+use crate::pause::PauseState;
+use crate::player::PlayerControl;
+use crate::ramp::Ramp;
+use crate::save::{data_dir, load_ron, save_ron};
+use crate::score::Judgement;
+use crate::songs::{find_song, CurrentSong, SelectedSong, SongDefinition};
+use crate::world::Stage;
+use crate::{GameMode, GameState};
+use bevy::prelude::*;
+use bevy_kira_audio::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const REPLAY_FILE: &str = "last_replay.ron";
+/// Scrubbing moves the replay by a bar of 4 beats
+pub const SCRUB_BEATS: u32 = 4;
+pub const REPLAY_SPEEDS: [f32; 3] = [1., 2., 4.];
+
+pub struct ReplayPlugin;
+
+/// Everything needed to play a run again: the board is deterministic given its
+/// seed and the inputs applied on each tick of the main clock.
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u32,
+    /// Id of the song, see [`SongDefinition`]
+    pub song: String,
+    /// Older replays were all played with the Normal rules
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    /// Older replays were all played without a ramp
+    #[serde(default)]
+    pub ramp: Option<Ramp>,
+    /// Older replays were all played on the default board
+    #[serde(default)]
+    pub geometry: BoardGeometry,
+    /// Inputs popped from the player buffer, with the tick they were applied
+    /// on. Idle ticks are not stored.
+    pub inputs: Vec<(u32, GameControl)>,
+    /// Offset to the beat and grade of the inputs, with the tick they were
+    /// applied on, so that the replay scores them the same. Older replays
+    /// were recorded without them.
+    #[serde(default)]
+    pub timings: Vec<(u32, f32, Judgement)>,
+}
+
+impl Replay {
+    pub fn input_at(&self, tick: u32) -> GameControl {
+        match self.inputs.binary_search_by_key(&tick, |x| x.0) {
+            Ok(ndx) => self.inputs[ndx].1,
+            Err(_) => GameControl::Idle,
+        }
+    }
+
+    pub fn timing_at(&self, tick: u32) -> Option<InputTiming> {
+        let ndx = self.timings.binary_search_by_key(&tick, |x| x.0).ok()?;
+        let (_, offset, judgement) = self.timings[ndx];
+        Some(InputTiming {
+            control: self.input_at(tick),
+            offset,
+            judgement,
+        })
+    }
+}
+
+/// Inputs of the current run, saved when it ends
+#[derive(Default, Resource)]
+pub struct ReplayRecorder {
+    pub inputs: Vec<(u32, GameControl)>,
+    pub timings: Vec<(u32, f32, Judgement)>,
+}
+
+/// Present when the game was started with `--replay <file>`. The recorded
+/// inputs are then fed to the player buffer instead of the keyboard ones.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub paused: bool,
+    pub speed_ndx: usize,
+}
+
+#[derive(Component)]
+struct ReplayHud;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>()
+            .add_systems(OnEnter(GameState::Menu), apply_replay_settings)
+            .add_systems(
+                OnEnter(GameState::Playing),
+                (
+                    start_recording,
+                    (reset_replay_playback, spawn_replay_hud)
+                        .run_if(resource_exists::<ReplayPlayback>()),
+                ),
+            )
+            .add_systems(
+                Update,
+                feed_replay_inputs
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(resource_exists::<ReplayPlayback>())
+                    .after(LogicSystem::TickClock)
+                    .before(LogicSystem::PopPlayerBuffer),
+            )
+            .add_systems(
+                Update,
+                (replay_controls, scrub_replay, update_replay_hud)
+                    .chain()
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(in_state(PauseState::Running))
+                    .run_if(resource_exists::<ReplayPlayback>())
+                    .before(LogicSystem::TickClock),
+            )
+            .add_systems(OnExit(GameState::Playing), despawn_replay_hud)
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                save_replay
+                    .run_if(not(resource_exists::<ReplayPlayback>()))
+                    .run_if(resource_equals(GameMode::Endless)),
+            );
+
+        if let Some(path) = replay_path_from_args() {
+            match load_ron::<Replay>(&path) {
+                Ok(replay) => {
+                    info!("playing replay {:?}", path);
+                    app.insert_resource(ReplayPlayback {
+                        replay,
+                        paused: false,
+                        speed_ndx: 0,
+                    });
+                }
+                Err(err) => error!("could not load the replay {:?}: {}", path, err),
+            }
+        }
+    }
+}
+
+fn replay_path_from_args() -> Option<PathBuf> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|x| x == "--replay")
+        .and_then(|ndx| args.get(ndx + 1))
+        .map(PathBuf::from)
+}
+
+/// The replayed run must use the recorded seed, song, difficulty, ramp and
+/// board
+#[allow(clippy::too_many_arguments)]
+fn apply_replay_settings(
+    playback: Option<Res<ReplayPlayback>>,
+    mut game_seed: ResMut<GameSeed>,
+    mut difficulty: ResMut<Difficulty>,
+    mut ramp_curve: ResMut<RampCurve>,
+    mut stage: ResMut<Stage>,
+    audio_assets: Res<AudioAssets>,
+    songs: Res<Assets<SongDefinition>>,
+    mut selected_song: ResMut<SelectedSong>,
+) {
+    if let Some(playback) = playback {
+        game_seed.seed = Some(playback.replay.seed);
+        *difficulty = playback.replay.difficulty.clone();
+        ramp_curve.ramp = playback.replay.ramp.clone();
+        stage.geometry = playback.replay.geometry.clone();
+        match find_song(&audio_assets, &songs, &playback.replay.song) {
+            Some(handle) => selected_song.handle = handle,
+            None => warn!("the song {} of the replay is missing", playback.replay.song),
+        }
+    }
+}
+
+fn start_recording(mut recorder: ResMut<ReplayRecorder>) {
+    recorder.inputs.clear();
+    recorder.timings.clear();
+}
+
+fn save_replay(
+    recorder: Res<ReplayRecorder>,
+    game_board: Res<GameBoard>,
+    current_song: CurrentSong,
+    difficulty: Res<Difficulty>,
+) {
+    let replay = Replay {
+        seed: game_board.rng.seed(),
+        song: current_song.get().id.clone(),
+        difficulty: difficulty.clone(),
+        ramp: game_board.ramp.clone(),
+        geometry: game_board.geometry().clone(),
+        inputs: recorder.inputs.clone(),
+        timings: recorder.timings.clone(),
+    };
+    let path = data_dir().join(REPLAY_FILE);
+    match save_ron(&path, &replay) {
+        Ok(()) => info!("replay saved to {:?}", path),
+        Err(err) => error!("could not save the replay {:?}: {}", path, err),
+    }
+}
+
+/// Every run starts at normal speed, so does its replay
+fn reset_replay_playback(mut playback: ResMut<ReplayPlayback>) {
+    playback.paused = false;
+    playback.speed_ndx = 0;
+}
+
+/// Put the recorded input of the coming tick in front of the player buffer,
+/// right before it is popped, with its recorded timing to be judged the same
+fn feed_replay_inputs(
+    main_clock: Res<MainClock>,
+    playback: Res<ReplayPlayback>,
+    mut player_control: ResMut<PlayerControl>,
+) {
+    if main_clock.main_timer.just_finished() {
+        player_control.input_buffer[0] = playback.replay.input_at(main_clock.tick);
+        player_control.input_timings[0] = playback.replay.timing_at(main_clock.tick);
+    }
+}
+
+/// Space pauses the replay, F cycles through the playback speeds
+fn replay_controls(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut main_clock: ResMut<MainClock>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    handle: Res<SongHandle>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        playback.paused = !playback.paused;
+        main_clock.paused = playback.paused;
+        if let Some(instance) = audio_instances.get_mut(&handle.song) {
+            if playback.paused {
+                instance.pause(AudioTween::default());
+            } else {
+                instance.resume(AudioTween::default());
+            }
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::F) {
+        playback.speed_ndx = (playback.speed_ndx + 1) % REPLAY_SPEEDS.len();
+        let speed = REPLAY_SPEEDS[playback.speed_ndx];
+        main_clock.playback_rate = speed;
+        if let Some(instance) = audio_instances.get_mut(&handle.song) {
+            instance.set_playback_rate(speed as f64, AudioTween::default());
+        }
+    }
+}
+
+/// Left and Right jump a bar backward or forward. The board is simulated again
+/// from the seed up to the target tick, then the clouds are respawned from it.
+#[allow(clippy::too_many_arguments)]
+fn scrub_replay(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    playback: Res<ReplayPlayback>,
+    asset_server: Res<AssetServer>,
+    handle: Res<SongHandle>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut main_clock: ResMut<MainClock>,
+    mut game_board: ResMut<GameBoard>,
+    mut player_control: ResMut<PlayerControl>,
+    cloud_query: Query<Entity, With<Cloud>>,
+) {
+    let scrub_length = SCRUB_BEATS * TIMER_SCALE_FACTOR as u32;
+    let target = if keyboard_input.just_pressed(KeyCode::Left) {
+        main_clock.tick.saturating_sub(scrub_length)
+    } else if keyboard_input.just_pressed(KeyCode::Right) {
+        main_clock.tick + scrub_length
+    } else {
+        return;
+    };
+    /* ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ Simulate the run ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ */
+    // Same rules, chart and ramp as the replayed board:
+    let mut board = Board::with_config(playback.replay.seed, game_board.config)
+        .with_geometry(game_board.geometry().clone())
+        .with_beatmap(game_board.beatmap.clone())
+        .with_ramp(game_board.ramp.clone());
+    while board.tick < target && board.loss_condition == LossCondition::NoLoss {
+        board.step(playback.replay.input_at(board.tick + 1));
+    }
+
+    /* ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ Rebuild the scene ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ */
+    for entity in cloud_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for cloud in board.clouds.iter() {
+        spawn_cloud(&mut commands, &asset_server, cloud, board.geometry());
+    }
+    player_control.player_pos = board.player_pos;
+    player_control.clear_buffer();
+    player_control.animation = AnimationState::Init;
+
+    /* ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ Move the clock and song ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ */
+    let song_pos = main_clock.jump_to_tick(board.tick);
+    if let Some(instance) = audio_instances.get_mut(&handle.song) {
+        instance.seek_to(song_pos as f64);
+    }
+    game_board.0 = board;
+}
+
+fn spawn_replay_hud(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font_assets.fira_sans.clone(),
+                font_size: 20.,
+                color: Color::rgb(0.9, 0.9, 0.9),
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.),
+            top: Val::Px(10.),
+            ..default()
+        }),
+        ReplayHud,
+    ));
+}
+
+fn update_replay_hud(
+    playback: Res<ReplayPlayback>,
+    main_clock: Res<MainClock>,
+    mut query: Query<&mut Text, With<ReplayHud>>,
+) {
+    let status = if playback.paused {
+        "paused".to_string()
+    } else {
+        format!("x{}", REPLAY_SPEEDS[playback.speed_ndx])
+    };
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "Replay {} - tick {}\nSpace: pause  F: speed  Left/Right: scrub",
+            status, main_clock.tick
+        );
+    }
+}
+
+fn despawn_replay_hud(mut commands: Commands, query: Query<Entity, With<ReplayHud>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,142 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Rewind of the runs which are not endless ones, like the puzzles. The board
+//! is saved at the start of every beat, and Backspace goes back one beat at a
+//! time: the clouds and the player ease back to where they were, along with
+//! the clock and the song.
+
+use crate::audio::SongHandle;
+use crate::board::Board;
+use crate::clouds::{cloud_texture, spawn_cloud, Cloud, GridPos, IsCooldown};
+use crate::logic::{GameBoard, LogicSystem, MainClock};
+use crate::pause::PauseState;
+use crate::player::PlayerControl;
+use crate::{GameMode, GameState};
+use bevy::prelude::*;
+use bevy_kira_audio::prelude::*;
+
+pub const REWIND_KEY: KeyCode = KeyCode::Back;
+/// Beats kept in the history, older ones cannot be rewound to
+pub const MAX_REWIND_BEATS: usize = 512;
+
+pub struct RewindPlugin;
+
+/// The board at the start of each beat played, the current one last
+#[derive(Resource, Default)]
+pub struct RewindHistory {
+    pub snapshots: Vec<Board>,
+}
+
+impl RewindHistory {
+    /// Drop the snapshots of the current beat and later ones, and return the
+    /// one of the previous beat. It stays in the history as the current beat.
+    pub fn rewind(&mut self, beat: u32) -> Option<Board> {
+        while self.snapshots.len() > 1 && self.snapshots.last().map(|x| x.beat) >= Some(beat) {
+            self.snapshots.pop();
+        }
+        self.snapshots.last().filter(|x| x.beat < beat).cloned()
+    }
+}
+
+impl Plugin for RewindPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RewindHistory>()
+            .add_systems(OnEnter(GameState::Playing), clear_history)
+            .add_systems(
+                Update,
+                record_snapshot
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(can_rewind)
+                    .after(LogicSystem::PopPlayerBuffer),
+            )
+            .add_systems(
+                Update,
+                rewind_beat
+                    .run_if(in_state(GameState::Playing))
+                    .run_if(in_state(PauseState::Running))
+                    .run_if(can_rewind)
+                    .before(LogicSystem::TickClock),
+            );
+    }
+}
+
+/// The endless runs are played as they come
+pub fn can_rewind(game_mode: Res<GameMode>) -> bool {
+    *game_mode != GameMode::Endless
+}
+
+fn clear_history(mut history: ResMut<RewindHistory>) {
+    history.snapshots.clear();
+}
+
+fn record_snapshot(game_board: Res<GameBoard>, mut history: ResMut<RewindHistory>) {
+    if history.snapshots.last().map(|x| x.beat) == Some(game_board.beat) {
+        return;
+    }
+    if history.snapshots.len() >= MAX_REWIND_BEATS {
+        history.snapshots.remove(0);
+    }
+    history.snapshots.push(game_board.0.clone());
+}
+
+/// Restore the board of the previous beat. The entities are moved rather than
+/// respawned, so that their easings play backward.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn rewind_beat(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    handle: Res<SongHandle>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut history: ResMut<RewindHistory>,
+    mut main_clock: ResMut<MainClock>,
+    mut game_board: ResMut<GameBoard>,
+    mut player_control: ResMut<PlayerControl>,
+    mut cloud_query: Query<(
+        Entity,
+        &mut Cloud,
+        &mut GridPos,
+        &mut IsCooldown,
+        &mut Handle<Image>,
+    )>,
+) {
+    if !keyboard_input.just_pressed(REWIND_KEY) {
+        return;
+    }
+    let Some(board) = history.rewind(game_board.beat) else {
+        return;
+    };
+
+    /* ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ Move the clouds back ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ */
+    for (entity, mut cloud, mut cloud_pos, mut is_cooling, mut texture) in cloud_query.iter_mut() {
+        // The clouds spawned since are gone:
+        let Some(old_cloud) = board.clouds.iter().find(|x| x.id == cloud.id) else {
+            commands.entity(entity).despawn();
+            continue;
+        };
+        if cloud_pos.pos != old_cloud.pos {
+            cloud_pos.old_pos = cloud_pos.pos;
+            cloud_pos.pos = old_cloud.pos;
+            // No burst, like a push:
+            cloud_pos.is_pushed = true;
+        }
+        cloud.dir = old_cloud.dir;
+        is_cooling.val = old_cloud.cooling;
+        *texture = asset_server.load(cloud_texture(old_cloud.dir, old_cloud.cooling));
+    }
+    // The ones which left the board since come back:
+    for old_cloud in board.clouds.iter() {
+        if !cloud_query.iter().any(|x| x.1.id == old_cloud.id) {
+            spawn_cloud(&mut commands, &asset_server, old_cloud, board.geometry());
+        }
+    }
+    player_control.player_pos = board.player_pos;
+    player_control.clear_buffer();
+
+    /* ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ Move the clock and song ▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓▓ */
+    let song_pos = main_clock.jump_to_tick(board.tick);
+    if let Some(instance) = audio_instances.get_mut(&handle.song) {
+        instance.seek_to(song_pos as f64);
+    }
+    game_board.0 = board;
+}
@@ -0,0 +1,48 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Files written by the game, stored as RON in the platform data directory.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use directories::ProjectDirs;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Directory where the game saves its files. Falls back to the working
+/// directory if the platform does not provide one.
+pub fn data_dir() -> PathBuf {
+    ProjectDirs::from("", "", "cloud_sweeper")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// The `assets` directory read by the asset server, found the same way: from
+/// `BEVY_ASSET_ROOT`, `CARGO_MANIFEST_DIR` or next to the executable
+pub fn assets_dir() -> PathBuf {
+    let base = std::env::var_os("BEVY_ASSET_ROOT")
+        .or_else(|| std::env::var_os("CARGO_MANIFEST_DIR"))
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|x| x.parent().map(Path::to_path_buf))
+        })
+        .unwrap_or_default();
+    base.join("assets")
+}
+
+pub fn save_ron<T: Serialize>(path: &Path, value: &T) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+pub fn load_ron<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn Error>> {
+    let content = fs::read_to_string(path)?;
+    Ok(ron::from_str(&content)?)
+}
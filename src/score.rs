@@ -0,0 +1,419 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+use crate::board::{BoardEvent, BoardGeometry};
+use crate::loading::FontAssets;
+use crate::logic::{BoardUpdate, GameBoard, InputTiming, LogicSystem};
+use crate::player::{Player, TILE_SIZE};
+use crate::world::{Stage, DISPLAY_RATIO};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::sprite::Anchor;
+use serde::{Deserialize, Serialize};
+
+// Points for each cloud pushed off the stage by the player:
+pub const PUSH_OFF_POINTS: u32 = 10;
+// Points for each extra cloud moved by a single push:
+pub const CHAIN_POINTS: u32 = 5;
+pub const ON_BEAT_POINTS: u32 = 1;
+// The multiplier increases every `COMBO_STEP` inputs played on the beat:
+pub const COMBO_STEP: u32 = 8;
+pub const MAX_MULTIPLIER: u32 = 4;
+pub const SCORE_FONT_SIZE: f32 = 32.;
+pub const SCORE_LAYER: f32 = 100.;
+pub const LEVEL_BANNER_FONT_SIZE: f32 = 64.;
+// Time the level banner stays on screen, in seconds:
+pub const LEVEL_BANNER_DURATION: f32 = 2.;
+pub const JUDGEMENT_FONT_SIZE: f32 = 24.;
+// Time a judgement stays above the player, in seconds:
+pub const JUDGEMENT_DURATION: f32 = 0.6;
+// Distance a judgement rises before it is gone, in pixels of the stage:
+pub const JUDGEMENT_RISE: f32 = 8.;
+
+pub struct ScorePlugin;
+
+/// Score of the current run
+#[derive(Default, Debug, Resource)]
+pub struct Score {
+    pub points: u32,
+    /// Number of consecutive inputs played on the beat
+    pub combo: u32,
+    pub max_combo: u32,
+    /// Clouds pushed off the stage by the player
+    pub swept: u32,
+    /// Pushes that moved several clouds at once
+    pub chains: u32,
+    /// Level of the ramp, see [`crate::ramp::Ramp`]
+    pub level: usize,
+    pub accuracy: Accuracy,
+}
+
+/// Grade of an input, from its offset to the beat, see
+/// [`JudgementWindows`](crate::difficulty::JudgementWindows)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Judgement {
+    Perfect,
+    Good,
+    Late,
+    Miss,
+}
+
+impl Judgement {
+    /// Whether the input counts as played on the beat
+    pub fn is_on_beat(self) -> bool {
+        matches!(self, Judgement::Perfect | Judgement::Good)
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            Judgement::Perfect => Color::GOLD,
+            Judgement::Good => Color::rgb(0.3, 0.9, 0.3),
+            Judgement::Late => Color::ORANGE,
+            Judgement::Miss => Color::rgb(0.9, 0.3, 0.3),
+        }
+    }
+}
+
+/// Number of inputs of each grade during a run
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Accuracy {
+    pub perfect: u32,
+    pub good: u32,
+    pub late: u32,
+    pub miss: u32,
+}
+
+impl Accuracy {
+    pub fn add(&mut self, judgement: Judgement) {
+        match judgement {
+            Judgement::Perfect => self.perfect += 1,
+            Judgement::Good => self.good += 1,
+            Judgement::Late => self.late += 1,
+            Judgement::Miss => self.miss += 1,
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.perfect + self.good + self.late + self.miss
+    }
+
+    /// Share of the inputs played on the beat, in percent
+    pub fn percent(&self) -> f32 {
+        match self.total() {
+            0 => 100.,
+            total => (self.perfect + self.good) as f32 * 100. / total as f32,
+        }
+    }
+}
+
+impl Score {
+    pub fn multiplier(&self) -> u32 {
+        (1 + self.combo / COMBO_STEP).min(MAX_MULTIPLIER)
+    }
+
+    /// Count an input of the player, one off the beat resets the combo
+    pub fn judge_input(&mut self, judgement: Judgement) {
+        self.accuracy.add(judgement);
+        if judgement.is_on_beat() {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+            self.points += ON_BEAT_POINTS * self.multiplier();
+        } else {
+            self.combo = 0;
+        }
+    }
+
+    /// Count the clouds pushed during one step of the board
+    pub fn add_pushes(&mut self, pushed: u32, pushed_off: u32) {
+        self.swept += pushed_off;
+        let mut points = pushed_off * PUSH_OFF_POINTS;
+        if pushed > 1 {
+            self.chains += 1;
+            points += (pushed - 1) * CHAIN_POINTS;
+        }
+        self.points += points * self.multiplier();
+    }
+
+    /// Count the events of one step of the board. Only the pushes of the
+    /// player count, see `BoardGeometry::is_sweep` for the clouds swept.
+    pub fn add_board_events<'a>(
+        &mut self,
+        events: impl IntoIterator<Item = &'a BoardEvent>,
+        geometry: &BoardGeometry,
+    ) {
+        let mut pushed = 0;
+        let mut pushed_off = 0;
+        for event in events {
+            match *event {
+                BoardEvent::CloudMoved {
+                    by_player: true, ..
+                }
+                | BoardEvent::CloudPushedOff {
+                    by_player: true, ..
+                } => {
+                    pushed += 1;
+                    if geometry.is_sweep(event) {
+                        pushed_off += 1;
+                    }
+                }
+                BoardEvent::LevelUp { level } => self.level = level,
+                _ => (),
+            }
+        }
+        if pushed > 0 {
+            self.add_pushes(pushed, pushed_off);
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct ScoreText;
+
+/// Announces a new level of the ramp in the middle of the stage
+#[derive(Component)]
+pub struct LevelBanner {
+    pub timer: Timer,
+}
+
+/// Grade of the last input, rising above the player
+#[derive(Component)]
+pub struct JudgementLabel {
+    pub timer: Timer,
+    pub start: Vec3,
+}
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Score>()
+            .add_systems(OnEnter(GameState::Playing), (reset_score, spawn_score_text))
+            .add_systems(
+                Update,
+                (
+                    score_board_events,
+                    update_score_text,
+                    spawn_level_banner,
+                    fade_level_banner,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing))
+                    .after(LogicSystem::PopPlayerBuffer),
+            )
+            .add_systems(
+                Update,
+                (spawn_judgement_label, float_judgement_label)
+                    .chain()
+                    .run_if(in_state(GameState::Playing))
+                    .after(LogicSystem::PopPlayerBuffer),
+            );
+    }
+}
+
+fn reset_score(mut score: ResMut<Score>) {
+    *score = Score::default();
+}
+
+/// The events of a step of the board are all sent during the same frame
+fn score_board_events(
+    mut board_events: EventReader<BoardUpdate>,
+    mut score: ResMut<Score>,
+    game_board: Res<GameBoard>,
+) {
+    score.add_board_events(board_events.iter().map(|x| &x.0), game_board.geometry());
+}
+
+/// The score is shown on the right of the mess bar
+fn spawn_score_text(mut commands: Commands, font_assets: Res<FontAssets>, stage: Res<Stage>) {
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: SCORE_FONT_SIZE,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+            ),
+            text_anchor: Anchor::TopLeft,
+            transform: Transform::from_xyz(
+                ((stage.geometry.width() as f32) / 2. + 1.) * TILE_SIZE,
+                ((stage.geometry.height() as f32) / 2.) * TILE_SIZE,
+                SCORE_LAYER,
+            )
+            .with_scale(Vec3::splat(DISPLAY_RATIO)),
+            ..default()
+        },
+        ScoreText,
+    ));
+}
+
+fn update_score_text(score: Res<Score>, mut query: Query<&mut Text, With<ScoreText>>) {
+    if !score.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "Score\n{}\nx{}\nCombo {}\nAccuracy {:.0}%",
+            score.points,
+            score.multiplier(),
+            score.combo,
+            score.accuracy.percent()
+        );
+        if score.level > 0 {
+            text.sections[0].value += &format!("\nLevel {}", score.level);
+        }
+    }
+}
+
+fn spawn_level_banner(
+    mut commands: Commands,
+    mut board_events: EventReader<BoardUpdate>,
+    font_assets: Res<FontAssets>,
+    query: Query<Entity, With<LevelBanner>>,
+) {
+    for event in board_events.iter() {
+        let BoardEvent::LevelUp { level } = **event else {
+            continue;
+        };
+        // Only the last level is announced:
+        for entity in query.iter() {
+            commands.entity(entity).despawn();
+        }
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    format!("Level {}!", level),
+                    TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size: LEVEL_BANNER_FONT_SIZE,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                ),
+                transform: Transform::from_xyz(0., 0., SCORE_LAYER)
+                    .with_scale(Vec3::splat(DISPLAY_RATIO)),
+                ..default()
+            },
+            LevelBanner {
+                timer: Timer::from_seconds(LEVEL_BANNER_DURATION, TimerMode::Once),
+            },
+        ));
+    }
+}
+
+/// The banner fades out, then goes away
+fn fade_level_banner(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut LevelBanner, &mut Text)>,
+) {
+    for (entity, mut banner, mut text) in query.iter_mut() {
+        banner.timer.tick(time.delta());
+        if banner.timer.finished() {
+            commands.entity(entity).despawn();
+        } else {
+            text.sections[0]
+                .style
+                .color
+                .set_a(banner.timer.percent_left());
+        }
+    }
+}
+
+/// Only the grade of the last input is shown
+fn spawn_judgement_label(
+    mut commands: Commands,
+    mut input_timings: EventReader<InputTiming>,
+    font_assets: Res<FontAssets>,
+    player_query: Query<&Transform, With<Player>>,
+    label_query: Query<Entity, With<JudgementLabel>>,
+) {
+    let Some(timing) = input_timings.iter().last() else {
+        return;
+    };
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    for entity in label_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    let start = Vec3::new(
+        player.translation.x,
+        player.translation.y + TILE_SIZE,
+        SCORE_LAYER,
+    );
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                format!("{:?}", timing.judgement),
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: JUDGEMENT_FONT_SIZE,
+                    color: timing.judgement.color(),
+                },
+            ),
+            transform: Transform::from_translation(start).with_scale(Vec3::splat(DISPLAY_RATIO)),
+            ..default()
+        },
+        JudgementLabel {
+            timer: Timer::from_seconds(JUDGEMENT_DURATION, TimerMode::Once),
+            start,
+        },
+    ));
+}
+
+fn float_judgement_label(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut JudgementLabel, &mut Transform, &mut Text)>,
+) {
+    for (entity, mut label, mut transform, mut text) in query.iter_mut() {
+        label.timer.tick(time.delta());
+        if label.timer.finished() {
+            commands.entity(entity).despawn();
+        } else {
+            transform.translation = label.start + Vec3::Y * JUDGEMENT_RISE * label.timer.percent();
+            text.sections[0]
+                .style
+                .color
+                .set_a(label.timer.percent_left());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::{Board, CloudDir, GameControl};
+
+    #[test]
+    fn pushing_a_cloud_into_a_hole_sweeps_it() {
+        let map = [
+            "~~~~~~~", "~~~~~~~", "~~###~~", "~~@#o~~", "~~###~~", "~~~~~~~", "~~~~~~~",
+        ];
+        let geometry = BoardGeometry::from_map(&map.map(|x| x.to_string())).unwrap();
+        let mut board = Board::new(0)
+            .with_geometry(geometry)
+            .without_spawns()
+            .with_clouds(&[([3, 3], CloudDir::Down)]);
+        let mut score = Score::default();
+        let events = board.step(GameControl::Right);
+        score.add_board_events(&events, board.geometry());
+        assert_eq!(score.swept, 1);
+        assert_eq!(score.swept, board.swept);
+        assert_eq!(score.points, PUSH_OFF_POINTS);
+    }
+
+    #[test]
+    fn clouds_pushed_by_other_clouds_do_not_count() {
+        // The up cloud pushes the left one out of the board on the first beat:
+        let mut board = Board::new(0)
+            .without_spawns()
+            .with_clouds(&[([5, 8], CloudDir::Up), ([5, 9], CloudDir::Left)]);
+        let mut score = Score::default();
+        for _ in 0..4 {
+            let events = board.step(GameControl::Idle);
+            score.add_board_events(&events, board.geometry());
+        }
+        assert_eq!(board.clouds.len(), 1);
+        assert_eq!(score.swept, 0);
+        assert_eq!(score.points, 0);
+    }
+}
@@ -0,0 +1,409 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Options of the player: volumes, window and input offset. They are read from
+//! `settings.ron` in the data directory by `main`, before the window is built,
+//! and changed on the settings screen, reached from the menu or the pause
+//! menu. Every change is applied and saved at once.
+
+use crate::loading::FontAssets;
+use crate::logic::MainClock;
+use crate::menu::{despawn_screen, spawn_menu_button};
+use crate::pause::PauseState;
+use crate::save::{data_dir, load_ron, save_ron};
+use crate::score::SCORE_LAYER;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+use bevy::window::{PresentMode, PrimaryWindow, WindowMode, WindowResolution};
+use serde::{Deserialize, Serialize};
+
+pub const SETTINGS_FILE: &str = "settings.ron";
+// Volumes are in percent, changed by steps of:
+pub const VOLUME_STEP: i32 = 10;
+// Input offset step and bound, in seconds:
+pub const INPUT_OFFSET_STEP: f32 = 0.005;
+pub const MAX_INPUT_OFFSET: f32 = 0.3;
+pub const WINDOW_MODES: [WindowMode; 3] = [
+    WindowMode::Windowed,
+    WindowMode::BorderlessFullscreen,
+    WindowMode::SizedFullscreen,
+];
+/// Sizes of the window, also used by the sized fullscreen
+pub const RESOLUTIONS: [(u32, u32); 4] = [(1280, 720), (1600, 900), (1920, 1080), (2560, 1440)];
+pub const SETTINGS_FONT_SIZE: f32 = 30.;
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const OVERLAY_COLOR: Color = Color::rgba(0., 0., 0., 0.9);
+
+pub struct SettingsPlugin;
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Volumes in percent, the music and the effects being scaled by the
+    /// master volume
+    pub master_volume: u32,
+    pub music_volume: u32,
+    pub sfx_volume: u32,
+    pub window_mode: WindowMode,
+    /// Width and height of the window, in pixels
+    pub resolution: (u32, u32),
+    pub vsync: bool,
+    /// Delay between a beat and the input of a player on it, in seconds,
+    /// positive when the inputs come late. Measured by [`crate::calibration`].
+    pub input_offset: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            master_volume: 100,
+            music_volume: 100,
+            sfx_volume: 100,
+            window_mode: WindowMode::BorderlessFullscreen,
+            resolution: RESOLUTIONS[0],
+            vsync: true,
+            input_offset: 0.,
+        }
+    }
+}
+
+/// The options of the settings screen
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SettingKind {
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    WindowMode,
+    Resolution,
+    Vsync,
+    InputOffset,
+}
+
+pub const SETTING_KINDS: [SettingKind; 7] = [
+    SettingKind::MasterVolume,
+    SettingKind::MusicVolume,
+    SettingKind::SfxVolume,
+    SettingKind::WindowMode,
+    SettingKind::Resolution,
+    SettingKind::Vsync,
+    SettingKind::InputOffset,
+];
+
+impl Settings {
+    /// Factor of the volume of the songs
+    pub fn music_volume(&self) -> f64 {
+        (self.master_volume * self.music_volume) as f64 / 10_000.
+    }
+
+    /// Factor of the volume of the samples played on the moves
+    pub fn sfx_volume(&self) -> f64 {
+        (self.master_volume * self.sfx_volume) as f64 / 10_000.
+    }
+
+    /// Factor of the volume of the sounds of the menus
+    pub fn ui_volume(&self) -> f64 {
+        self.master_volume as f64 / 100.
+    }
+
+    /// The primary window, as described by the settings
+    pub fn window(&self) -> Window {
+        Window {
+            title: "Cloud Sweeper".into(),
+            mode: self.window_mode,
+            resolution: WindowResolution::new(self.resolution.0 as f32, self.resolution.1 as f32)
+                .with_scale_factor_override(1.0),
+            present_mode: self.present_mode(),
+            ..default()
+        }
+    }
+
+    pub fn present_mode(&self) -> PresentMode {
+        if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        }
+    }
+
+    /// Move an option by a number of steps, the lists wrapping around
+    pub fn adjust(&mut self, kind: SettingKind, step: i32) {
+        let volume = |x: u32| (x as i32 + step * VOLUME_STEP).clamp(0, 100) as u32;
+        match kind {
+            SettingKind::MasterVolume => self.master_volume = volume(self.master_volume),
+            SettingKind::MusicVolume => self.music_volume = volume(self.music_volume),
+            SettingKind::SfxVolume => self.sfx_volume = volume(self.sfx_volume),
+            SettingKind::WindowMode => {
+                self.window_mode = cycle(&WINDOW_MODES, &self.window_mode, step)
+            }
+            SettingKind::Resolution => {
+                self.resolution = cycle(&RESOLUTIONS, &self.resolution, step)
+            }
+            SettingKind::Vsync => self.vsync = !self.vsync,
+            SettingKind::InputOffset => {
+                let offset = self.input_offset + step as f32 * INPUT_OFFSET_STEP;
+                // Rounded to the millisecond, so that the steps do not drift:
+                self.input_offset =
+                    ((offset * 1000.).round() / 1000.).clamp(-MAX_INPUT_OFFSET, MAX_INPUT_OFFSET);
+            }
+        }
+    }
+
+    /// Line of an option on the settings screen
+    pub fn describe(&self, kind: SettingKind) -> String {
+        match kind {
+            SettingKind::MasterVolume => format!("Master volume: {}%", self.master_volume),
+            SettingKind::MusicVolume => format!("Music volume: {}%", self.music_volume),
+            SettingKind::SfxVolume => format!("Effects volume: {}%", self.sfx_volume),
+            SettingKind::WindowMode => format!(
+                "Window: {}",
+                match self.window_mode {
+                    WindowMode::Windowed => "windowed",
+                    WindowMode::BorderlessFullscreen => "borderless",
+                    WindowMode::SizedFullscreen | WindowMode::Fullscreen => "fullscreen",
+                }
+            ),
+            SettingKind::Resolution => {
+                format!("Resolution: {}x{}", self.resolution.0, self.resolution.1)
+            }
+            SettingKind::Vsync => format!("Vsync: {}", if self.vsync { "on" } else { "off" }),
+            SettingKind::InputOffset => {
+                format!("Input offset: {:+.0} ms", self.input_offset * 1000.)
+            }
+        }
+    }
+}
+
+/// The value `step` places after `current` in `values`, from the first one if
+/// `current` is not in the list
+fn cycle<T: PartialEq + Copy>(values: &[T], current: &T, step: i32) -> T {
+    let ndx = values.iter().position(|x| x == current).unwrap_or(0) as i32;
+    values[(ndx + step).rem_euclid(values.len() as i32) as usize]
+}
+
+/// Read the settings, writing the default ones the first time. Called before
+/// the app is built, to open the window with them.
+pub fn load_settings() -> Settings {
+    let path = data_dir().join(SETTINGS_FILE);
+    if path.exists() {
+        match load_ron::<Settings>(&path) {
+            Ok(settings) => return settings,
+            Err(err) => error!("could not load the settings {:?}: {}", path, err),
+        }
+    }
+    let settings = Settings::default();
+    if !path.exists() {
+        save_settings(&settings);
+    }
+    settings
+}
+
+pub fn save_settings(settings: &Settings) {
+    let path = data_dir().join(SETTINGS_FILE);
+    if let Err(err) = save_ron(&path, settings) {
+        error!("could not save the settings {:?}: {}", path, err);
+    }
+}
+
+/// Root of the settings screen, over the pause menu when opened from it
+#[derive(Component)]
+struct SettingsPanel;
+#[derive(Component)]
+struct SettingButton {
+    kind: SettingKind,
+    step: i32,
+}
+#[derive(Component)]
+struct SettingText(SettingKind);
+#[derive(Component)]
+struct CloseSettingsButton;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        // The settings are usually inserted by `main`, which opens the window
+        // with them:
+        if !app.world.contains_resource::<Settings>() {
+            app.insert_resource(load_settings());
+        }
+        app.add_systems(OnEnter(GameState::Settings), spawn_settings_screen)
+            .add_systems(OnExit(GameState::Settings), despawn_screen::<SettingsPanel>)
+            .add_systems(OnExit(PauseState::Paused), despawn_screen::<SettingsPanel>)
+            .add_systems(
+                Update,
+                (
+                    click_setting_button,
+                    update_settings_text,
+                    click_close_button,
+                )
+                    .chain()
+                    .run_if(any_with_component::<SettingsPanel>()),
+            )
+            .add_systems(
+                Update,
+                (
+                    apply_window_settings,
+                    apply_input_offset.run_if(resource_exists::<MainClock>()),
+                )
+                    .run_if(resource_changed::<Settings>()),
+            );
+    }
+}
+
+fn spawn_settings_screen(mut commands: Commands, font_assets: Res<FontAssets>) {
+    spawn_settings_panel(&mut commands, &font_assets);
+}
+
+/// Spawn the settings screen, on top of the other nodes
+pub fn spawn_settings_panel(commands: &mut Commands, font_assets: &FontAssets) {
+    let style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: SETTINGS_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+    let arrow_style = Style {
+        width: Val::Px(50.0),
+        height: Val::Px(50.0),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..Default::default()
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.),
+                height: Val::Percent(100.),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: OVERLAY_COLOR.into(),
+            // Above the pause menu, whose buttons it covers:
+            z_index: ZIndex::Global(SCORE_LAYER as i32 + 1),
+            focus_policy: FocusPolicy::Block,
+            ..default()
+        })
+        .insert(SettingsPanel)
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section("Settings", style.clone()).with_style(Style {
+                    margin: UiRect::all(Val::Px(20.)),
+                    ..default()
+                }),
+            );
+            for kind in SETTING_KINDS {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            margin: UiRect::bottom(Val::Px(10.)),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn(ButtonBundle {
+                                style: arrow_style.clone(),
+                                ..Default::default()
+                            })
+                            .insert(SettingButton { kind, step: -1 })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section("<", style.clone()));
+                            });
+                        parent
+                            .spawn(
+                                TextBundle::from_section("", style.clone())
+                                    .with_text_alignment(TextAlignment::Center)
+                                    .with_style(Style {
+                                        width: Val::Px(400.),
+                                        justify_content: JustifyContent::Center,
+                                        ..default()
+                                    }),
+                            )
+                            .insert(SettingText(kind));
+                        parent
+                            .spawn(ButtonBundle {
+                                style: arrow_style.clone(),
+                                ..Default::default()
+                            })
+                            .insert(SettingButton { kind, step: 1 })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(">", style.clone()));
+                            });
+                    });
+            }
+            spawn_menu_button(parent, &style, "Back", CloseSettingsButton);
+        });
+}
+
+fn click_setting_button(
+    mut settings: ResMut<Settings>,
+    interaction_query: Query<(&Interaction, &SettingButton), Changed<Interaction>>,
+) {
+    let mut changed = false;
+    for (interaction, button) in &interaction_query {
+        if *interaction == Interaction::Pressed {
+            settings.adjust(button.kind, button.step);
+            changed = true;
+        }
+    }
+    if changed {
+        save_settings(&settings);
+    }
+}
+
+fn update_settings_text(settings: Res<Settings>, mut query: Query<(&mut Text, &SettingText)>) {
+    for (mut text, setting_text) in query.iter_mut() {
+        text.sections[0].value = settings.describe(setting_text.0);
+    }
+}
+
+/// Back to the menu from the settings screen, or to the pause menu
+#[allow(clippy::type_complexity)]
+fn click_close_button(
+    mut commands: Commands,
+    game_state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    interaction_query: Query<&Interaction, (Changed<Interaction>, With<CloseSettingsButton>)>,
+    panel_query: Query<Entity, With<SettingsPanel>>,
+) {
+    if !interaction_query.iter().any(|x| *x == Interaction::Pressed) {
+        return;
+    }
+    if *game_state.get() == GameState::Settings {
+        next_state.set(GameState::Menu);
+    } else {
+        for entity in &panel_query {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn apply_window_settings(
+    settings: Res<Settings>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let Ok(mut window) = window_query.get_single_mut() else {
+        return;
+    };
+    let (width, height) = settings.resolution;
+    if window.mode != settings.window_mode {
+        window.mode = settings.window_mode;
+    }
+    // The borderless window takes the size of the monitor:
+    let sized = settings.window_mode != WindowMode::BorderlessFullscreen;
+    if sized
+        && (window.resolution.physical_width() != width
+            || window.resolution.physical_height() != height)
+    {
+        window.resolution.set_physical_resolution(width, height);
+    }
+    if window.present_mode != settings.present_mode() {
+        window.present_mode = settings.present_mode();
+    }
+}
+
+/// A change from the pause menu applies to the run being played
+fn apply_input_offset(settings: Res<Settings>, mut main_clock: ResMut<MainClock>) {
+    main_clock.input_offset = settings.input_offset;
+}
@@ -0,0 +1,245 @@
+#![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+
+//! Songs are described by `assets/songs/*.song.ron` files, all loaded with
+//! the other assets. Adding a song only needs a new file:
+//!
+//! ```ron
+//! (
+//!     name: "Song 2",
+//!     artist: "Hstick",
+//!     track: "audio/song_2/song_full.wav",
+//!     bpm: 100.,
+//!     // The song loops on [intro_length, intro_length + loop_length]:
+//!     intro_length: 2.4,
+//!     loop_length: 57.6,
+//!     // Start of the snippet played in the menu:
+//!     preview_start: 10.,
+//!     move_sample: "audio/song_2/sample_1_c.wav",
+//!     push_samples: (
+//!         up: "audio/song_2/sample_1_a.wav",
+//!         down: "audio/song_2/sample_1_b.wav",
+//!         left: "audio/song_2/sample_1_c.wav",
+//!         right: "audio/song_2/sample_1_d.wav",
+//!     ),
+//!     // Played one after the other:
+//!     special_samples: ["audio/song_2/sample_3_a.wav", "audio/song_2/sample_3_c.wav"],
+//!     // Optional stinger of the game over:
+//!     game_over_sample: "audio/song_2/sample_3.wav",
+//!     // Optional chart of the clouds, see `beatmap`:
+//!     beatmap: "beatmaps/song_2.beatmap.ron",
+//! )
+//! ```
+
+use crate::beatmap::Beatmap;
+use crate::loading::AudioAssets;
+use crate::GameState;
+use bevy::asset::{AssetLoader, AssetPath, BoxedFuture, LoadContext, LoadedAsset};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy_kira_audio::AudioSource;
+use ron::extensions::Extensions;
+use serde::Deserialize;
+
+// Song selected when nothing else has been chosen:
+pub const DEFAULT_SONG: &str = "song_2";
+
+pub struct SongsPlugin;
+
+/// Content of a `.song.ron` file
+#[derive(Deserialize)]
+struct SongFile {
+    name: String,
+    #[serde(default)]
+    artist: String,
+    track: String,
+    bpm: f32,
+    intro_length: f32,
+    loop_length: f32,
+    preview_start: Option<f32>,
+    #[serde(default = "default_volume")]
+    volume: f64,
+    move_sample: String,
+    push_samples: PushSamples<String>,
+    special_samples: Vec<String>,
+    game_over_sample: Option<String>,
+    beatmap: Option<String>,
+}
+
+fn default_volume() -> f64 {
+    0.5
+}
+
+/// Sample played when pushing a cloud, for each direction of the input
+#[derive(Deserialize, Debug, Clone)]
+pub struct PushSamples<T> {
+    pub up: T,
+    pub down: T,
+    pub left: T,
+    pub right: T,
+}
+
+#[derive(TypeUuid, TypePath, Debug)]
+#[uuid = "5b6bd7c4-7d0f-4c5e-9a3e-2f1f0b7c9d51"]
+pub struct SongDefinition {
+    /// Name of the file, without the extension. Used to save replays and
+    /// scores.
+    pub id: String,
+    pub name: String,
+    pub artist: String,
+    pub track: Handle<AudioSource>,
+    pub bpm: f32,
+    pub beat_length: f32,
+    pub intro_length: f32,
+    pub loop_length: f32,
+    pub preview_start: f32,
+    pub volume: f64,
+    pub move_sample: Handle<AudioSource>,
+    pub push_samples: PushSamples<Handle<AudioSource>>,
+    pub special_samples: Vec<Handle<AudioSource>>,
+    /// Stinger played over the fade out of the song when the run is over
+    pub game_over_sample: Option<Handle<AudioSource>>,
+    pub beatmap: Option<Beatmap>,
+    /// Asset path of the beatmap, where the editor saves it
+    pub beatmap_path: Option<String>,
+}
+
+#[derive(Default)]
+struct SongLoader;
+
+impl AssetLoader for SongLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            // The optional fields are written without `Some`:
+            let file: SongFile = ron::Options::default()
+                .with_default_extension(Extensions::IMPLICIT_SOME)
+                .from_bytes(bytes)?;
+            // The chart is only read by the rules, it is part of the song:
+            let beatmap = match &file.beatmap {
+                Some(path) => Some(ron::de::from_bytes(
+                    &load_context.read_asset_bytes(path).await?,
+                )?),
+                None => None,
+            };
+            let id = load_context
+                .path()
+                .file_name()
+                .and_then(|x| x.to_str())
+                .and_then(|x| x.strip_suffix(".song.ron"))
+                .unwrap_or_default()
+                .to_string();
+
+            // The audio files are loaded as dependencies of the song:
+            let mut dependencies = Vec::new();
+            let mut load_audio = |path: &String| -> Handle<AudioSource> {
+                dependencies.push(AssetPath::from(path.clone()));
+                load_context.get_handle(AssetPath::from(path.as_str()))
+            };
+            let definition = SongDefinition {
+                id,
+                name: file.name,
+                artist: file.artist,
+                track: load_audio(&file.track),
+                bpm: file.bpm,
+                beat_length: 60. / file.bpm,
+                intro_length: file.intro_length,
+                loop_length: file.loop_length,
+                // By default, the preview starts after the intro:
+                preview_start: file.preview_start.unwrap_or(file.intro_length),
+                volume: file.volume,
+                move_sample: load_audio(&file.move_sample),
+                push_samples: PushSamples {
+                    up: load_audio(&file.push_samples.up),
+                    down: load_audio(&file.push_samples.down),
+                    left: load_audio(&file.push_samples.left),
+                    right: load_audio(&file.push_samples.right),
+                },
+                special_samples: file.special_samples.iter().map(&mut load_audio).collect(),
+                game_over_sample: file.game_over_sample.as_ref().map(&mut load_audio),
+                beatmap,
+                beatmap_path: file.beatmap,
+            };
+            load_context
+                .set_default_asset(LoadedAsset::new(definition).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["song.ron"]
+    }
+}
+
+/// Song played during the runs
+#[derive(Default, Resource)]
+pub struct SelectedSong {
+    pub handle: Handle<SongDefinition>,
+}
+
+/// Gives access to the definition of the selected song
+#[derive(SystemParam)]
+pub struct CurrentSong<'w> {
+    selected: Res<'w, SelectedSong>,
+    songs: Res<'w, Assets<SongDefinition>>,
+}
+
+impl CurrentSong<'_> {
+    pub fn get(&self) -> &SongDefinition {
+        self.songs
+            .get(&self.selected.handle)
+            .expect("the selected song is loaded")
+    }
+}
+
+impl Plugin for SongsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<SongDefinition>()
+            .init_asset_loader::<SongLoader>()
+            .init_resource::<SelectedSong>()
+            .add_systems(OnExit(GameState::Loading), select_default_song);
+    }
+}
+
+/// Find a loaded song from its id
+pub fn find_song(
+    audio_assets: &AudioAssets,
+    songs: &Assets<SongDefinition>,
+    id: &str,
+) -> Option<Handle<SongDefinition>> {
+    audio_assets
+        .songs
+        .iter()
+        .find(|x| songs.get(x).map(|song| song.id == id).unwrap_or(false))
+        .cloned()
+}
+
+/// The loaded songs, in the order of their ids
+pub fn sorted_songs(
+    audio_assets: &AudioAssets,
+    songs: &Assets<SongDefinition>,
+) -> Vec<Handle<SongDefinition>> {
+    let mut handles: Vec<_> = audio_assets
+        .songs
+        .iter()
+        .filter(|x| songs.get(x).is_some())
+        .cloned()
+        .collect();
+    handles.sort_by_key(|x| songs.get(x).map(|song| song.id.clone()));
+    handles
+}
+
+fn select_default_song(
+    audio_assets: Res<AudioAssets>,
+    songs: Res<Assets<SongDefinition>>,
+    mut selected_song: ResMut<SelectedSong>,
+) {
+    if let Some(handle) = find_song(&audio_assets, &songs, DEFAULT_SONG)
+        .or_else(|| audio_assets.songs.first().cloned())
+    {
+        selected_song.handle = handle;
+    }
+}
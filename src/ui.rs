@@ -4,15 +4,19 @@ use bevy_ecs_tilemap::tiles::TileStorage;
 use colored::*;
 
 use crate::{
-    logic::{GridState, LossCause, LossCondition, CLOUD_COUNT_LOSE_COND},
+    board::{BoardGeometry, LossCondition},
+    logic::{GameBoard, LossCause},
     menu::GAMEOVER_MESS_BLINK_DURATION,
     player::TILE_SIZE,
-    world::{AllTiles, TileMapEntity, LEVEL_SIZE},
+    world::{AllTiles, Stage, TileMapEntity},
     GameState,
 };
 
 pub struct UiPlugin;
 
+// Colours of the mess bar in `mess_bar.png`, from the bottom to the top:
+pub const MESS_BAR_COLORS: u32 = 5;
+
 #[derive(Component, Default)]
 pub struct MessBar {
     pub counter: usize,
@@ -31,13 +35,14 @@ impl Plugin for UiPlugin {
     }
 }
 
-fn setup_mess_bar(mut commands: Commands, asset_server: Res<AssetServer>) {
+/// The mess bar is as high as the board, on its right
+fn setup_mess_bar(mut commands: Commands, asset_server: Res<AssetServer>, stage: Res<Stage>) {
     let texture_handle: Handle<Image> = asset_server.load("textures/mess_bar.png");
     // let texture_atlas =
     //     TextureAtlas::from_grid(texture_handle, Vec2::new(TILE_SIZE, TILE_SIZE), 6, 1);
     let tilemap_size = TilemapSize {
         x: 1,
-        y: LEVEL_SIZE,
+        y: stage.geometry.height(),
     };
     let tile_size = TilemapTileSize {
         x: TILE_SIZE,
@@ -58,7 +63,7 @@ fn setup_mess_bar(mut commands: Commands, asset_server: Res<AssetServer>) {
             .spawn_empty()
             .insert(TileBundle {
                 position: tile_pos,
-                texture_index: TileTextureIndex(1 + y / 2),
+                texture_index: TileTextureIndex(1 + y * MESS_BAR_COLORS / tilemap_size.y),
                 tilemap_id: TilemapId(tilemap_entity),
                 ..Default::default()
             })
@@ -79,17 +84,17 @@ fn setup_mess_bar(mut commands: Commands, asset_server: Res<AssetServer>) {
             storage: tile_storage,
             texture: TilemapTexture::Single(texture_handle),
             tile_size,
-            transform: get_mess_tile_pos(0, 100.),
+            transform: get_mess_tile_pos(&stage.geometry, 0, 100.),
             ..Default::default()
         })
         .insert(MessBar::default());
 }
 
 /// Method to compute the positions of the blocks of the load bar
-pub fn get_mess_tile_pos(ndx: u32, z: f32) -> Transform {
+pub fn get_mess_tile_pos(geometry: &BoardGeometry, ndx: u32, z: f32) -> Transform {
     Transform::from_xyz(
-        ((LEVEL_SIZE as f32) / 2.) * TILE_SIZE,
-        -TILE_SIZE * (LEVEL_SIZE as f32 - 1.) / 2. + ndx as f32,
+        ((geometry.width() as f32) / 2.) * TILE_SIZE,
+        -TILE_SIZE * (geometry.height() as f32 - 1.) / 2. + ndx as f32,
         z,
     )
 }
@@ -97,14 +102,15 @@ pub fn get_mess_tile_pos(ndx: u32, z: f32) -> Transform {
 fn update_mess_bar(
     mut next_state: ResMut<NextState<GameState>>,
     mut commands: Commands,
-    mut grid_state: ResMut<GridState>,
+    game_board: Res<GameBoard>,
     mess_query: Query<&mut MessBar>,
     mut tile_query: Query<(&TilePos, &mut TileVisible, Entity), With<MessTile>>,
 ) {
     // The counter is duplicated...
     let mess_counter = mess_query.into_iter().collect::<Vec<&MessBar>>()[0].counter;
 
-    let threshold: f32 = mess_counter as f32 * LEVEL_SIZE as f32 / CLOUD_COUNT_LOSE_COND as f32;
+    let threshold: f32 = mess_counter as f32 * game_board.geometry().height() as f32
+        / game_board.config.mess_limit as f32;
 
     for (pos, mut vis, _) in tile_query.iter_mut() {
         if pos.y <= threshold as u32 {
@@ -114,11 +120,10 @@ fn update_mess_bar(
         }
     }
 
-    if mess_counter > CLOUD_COUNT_LOSE_COND {
+    if game_board.loss_condition == LossCondition::TooMessy {
         for (_, _, entity) in tile_query.iter_mut() {
             commands.entity(entity).insert(LossCause);
         }
-        grid_state.loss_condition = LossCondition::TooMessy;
         next_state.set(GameState::GameOver);
     }
 }
@@ -1,8 +1,32 @@
 #![cfg_attr(debug_assertions, allow(dead_code, unused_imports))]
+//! The tiles of the board, laid out following the stage drawn in
+//! `assets/default.stage.ron`, one string per row:
+//!
+//! ```ron
+//! (
+//!     // `~` sky, `#` platform, `o` hole, `X` wall, `@` start of the player:
+//!     map: [
+//!         "~~~~~~~~",
+//!         "~~~~~~~~",
+//!         "~~o##~~~",
+//!         "~~#@X#~~",
+//!         "~~####~~",
+//!         "~~~~~~~~",
+//!         "~~~~~~~~",
+//!     ],
+//! )
+//! ```
+//!
+//! Other stages can be found in `assets/stages`.
+
 // use crate::loading::TextureAssets;
+pub use crate::board::{BoardGeometry, StageMap, StageTile, LEVEL_SIZE};
+use crate::loading::DataAssets;
 use crate::player::TILE_SIZE;
 use crate::GameState;
+use bevy::asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset};
 use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
 use bevy_ecs_tilemap::helpers::geometry::get_tilemap_center_transform;
 use bevy_ecs_tilemap::map::TilemapTexture;
 use bevy_ecs_tilemap::prelude::*;
@@ -23,23 +47,104 @@ pub struct Sky;
 #[derive(Component)]
 pub struct Platform;
 
+#[derive(Component)]
+pub struct Hole;
+
+#[derive(Component)]
+pub struct Wall;
+
 #[derive(Component)]
 pub struct AllTiles;
 
 #[derive(Component)]
 pub struct TileMapEntity;
 
-pub const LEVEL_SIZE: u32 = 10;
-pub const STAGE_WIDTH: u32 = 6;
-pub const STAGE_BL: [u32; 2] = [2, 2];
-pub const STAGE_UR: [u32; 2] = [7, 7];
 // pub const CAMERA_LAYER: f32 = 500.;
 pub const DISPLAY_RATIO: f32 = 1. / 4.;
 
+/// Index of each kind of tile in `tiles.png`
+pub fn tile_texture_index(tile: StageTile) -> u32 {
+    match tile {
+        StageTile::Platform => 0,
+        StageTile::Sky => 1,
+        StageTile::Hole => 6,
+        StageTile::Wall => 7,
+    }
+}
+
+/// Geometry of the board of the next runs
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Stage {
+    pub geometry: BoardGeometry,
+}
+
+/// Content of a `.stage.ron` file
+#[derive(TypeUuid, TypePath, Debug)]
+#[uuid = "412e2f4a-3220-49a4-b99a-c45773278ef8"]
+pub struct StageAsset(pub StageMap);
+
+#[derive(Default)]
+struct StageLoader;
+
+impl AssetLoader for StageLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let stage_map: StageMap = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(StageAsset(stage_map)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["stage.ron"]
+    }
+}
+
+/// The stage of `assets/default.stage.ron`, the default one if it is invalid
+pub fn load_stage(data_assets: &DataAssets, stages: &Assets<StageAsset>) -> Stage {
+    let Some(stage_map) = stages.get(&data_assets.stage) else {
+        warn!("could not load the stage");
+        return Stage::default();
+    };
+    match BoardGeometry::from_map(&stage_map.0.map) {
+        Ok(geometry) => Stage { geometry },
+        Err(err) => {
+            warn!("invalid stage: {}", err);
+            Stage::default()
+        }
+    }
+}
+
+fn set_up_stage(
+    data_assets: Res<DataAssets>,
+    stages: Res<Assets<StageAsset>>,
+    mut stage: ResMut<Stage>,
+) {
+    *stage = load_stage(&data_assets, &stages);
+}
+
+/// Position of a tile relative to the centre of the board, in tiles
+pub fn tile_offset(geometry: &BoardGeometry, tile: [i8; 2]) -> Vec2 {
+    Vec2::new(
+        tile[0] as f32 - geometry.width() as f32 / 2.,
+        tile[1] as f32 - geometry.height() as f32 / 2.,
+    )
+}
+
 /// This plugin handles world related stuff: background, cloud movement,...
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::Playing), setup_world)
+        app.add_asset::<StageAsset>()
+            .init_asset_loader::<StageLoader>()
+            .init_resource::<Stage>()
+            .add_systems(OnExit(GameState::Loading), set_up_stage)
+            .add_systems(OnEnter(GameState::Playing), (setup_world, fit_camera))
+            .add_systems(OnEnter(GameState::Editor), (setup_world, fit_camera))
+            .add_systems(OnEnter(GameState::Menu), reset_camera)
             // .add_enter_system(GameState::Playing, spawn_world)
             .add_systems(Update, update_world.run_if(in_state(GameState::Playing)))
             // .insert_resource(ImageSettings::default_nearest())
@@ -47,14 +152,30 @@ impl Plugin for WorldPlugin {
     }
 }
 
-fn setup_world(mut commands: Commands, asset_server: Res<AssetServer>) {
+/// The camera zooms out so that bigger boards fit on the screen like the
+/// default one
+fn fit_camera(stage: Res<Stage>, mut camera_query: Query<&mut Transform, With<Camera2d>>) {
+    let size = stage.geometry.width().max(stage.geometry.height());
+    let scale = DISPLAY_RATIO * size.max(LEVEL_SIZE) as f32 / LEVEL_SIZE as f32;
+    for mut transform in camera_query.iter_mut() {
+        transform.scale = Vec3::new(scale, scale, 1.);
+    }
+}
+
+fn reset_camera(mut camera_query: Query<&mut Transform, With<Camera2d>>) {
+    for mut transform in camera_query.iter_mut() {
+        transform.scale = Vec3::new(DISPLAY_RATIO, DISPLAY_RATIO, 1.);
+    }
+}
+
+fn setup_world(mut commands: Commands, asset_server: Res<AssetServer>, stage: Res<Stage>) {
     // commands.spawn_bundle(Camera2dBundle::default());
 
     let texture_handle: Handle<Image> = asset_server.load("textures/tiles.png");
 
     let tilemap_size = TilemapSize {
-        x: LEVEL_SIZE,
-        y: LEVEL_SIZE,
+        x: stage.geometry.width(),
+        y: stage.geometry.height(),
     };
     let tilemap_entity = commands.spawn_empty().insert(TileMapEntity).id();
     let mut tile_storage = TileStorage::empty(tilemap_size);
@@ -73,18 +194,16 @@ fn setup_world(mut commands: Commands, asset_server: Res<AssetServer>) {
                 .insert(AllTiles)
                 .id();
             commands.entity(tilemap_entity).add_child(tile_entity);
-            if (STAGE_BL[0] <= x) && (x <= STAGE_UR[0]) && (STAGE_BL[1] <= y) && (y <= STAGE_UR[1])
-            {
-                commands
-                    .entity(tile_entity)
-                    .insert(Platform)
-                    .insert(TileTextureIndex(0));
-            } else {
-                commands
-                    .entity(tile_entity)
-                    .insert(Sky)
-                    .insert(TileTextureIndex(1));
-            }
+            let kind = stage.geometry.tile([x as i8, y as i8]);
+            commands
+                .entity(tile_entity)
+                .insert(TileTextureIndex(tile_texture_index(kind)));
+            match kind {
+                StageTile::Sky => commands.entity(tile_entity).insert(Sky),
+                StageTile::Platform => commands.entity(tile_entity).insert(Platform),
+                StageTile::Hole => commands.entity(tile_entity).insert(Hole),
+                StageTile::Wall => commands.entity(tile_entity).insert(Wall),
+            };
             tile_storage.set(&tile_pos, tile_entity);
         }
     }